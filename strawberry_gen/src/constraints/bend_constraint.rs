@@ -0,0 +1,54 @@
+use bevy::prelude::*;
+
+use crate::ParticlePosition;
+
+/// Keeps the angle at the joint `b` between the segments `a`-`b` and `b`-`c` at its rest angle.
+#[derive(Component, Debug, Reflect)]
+pub struct BendConstraint {
+    /// Angle in radians between the directions of the two segments, zero when they are aligned.
+    pub rest_angle: f32,
+    pub compliance: f32,
+}
+impl BendConstraint {
+    pub fn from_particles(
+        a: &ParticlePosition,
+        b: &ParticlePosition,
+        c: &ParticlePosition,
+    ) -> Self {
+        Self::from_rest_angle(Self::angle(a, b, c))
+    }
+    pub fn from_rest_angle(rest_angle: f32) -> Self {
+        Self {
+            rest_angle,
+            compliance: 0.1,
+        }
+    }
+}
+
+impl BendConstraint {
+    pub fn get_compliance(&self) -> f32 {
+        self.compliance
+    }
+    pub fn angle(a: &ParticlePosition, b: &ParticlePosition, c: &ParticlePosition) -> f32 {
+        (**b - **a).angle_between(**c - **b)
+    }
+    pub fn compute_stress(
+        &self,
+        a: &ParticlePosition,
+        b: &ParticlePosition,
+        c: &ParticlePosition,
+    ) -> f32 {
+        (Self::angle(a, b, c) - self.rest_angle) * self.get_compliance()
+    }
+    /// Turns `a` and `c` around `b` in the plane of the joint, each by half the difference to
+    /// the rest angle.
+    pub fn solve(&self, a: &mut ParticlePosition, b: &ParticlePosition, c: &mut ParticlePosition) {
+        let (first, second) = (**b - **a, **c - **b);
+        let Some(axis) = first.cross(second).try_normalize() else {
+            return;
+        };
+        let difference = self.rest_angle - first.angle_between(second);
+        a.0 = **b - Quat::from_axis_angle(axis, -difference * 0.5) * first;
+        c.0 = **b + Quat::from_axis_angle(axis, difference * 0.5) * second;
+    }
+}
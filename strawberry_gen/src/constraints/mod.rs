@@ -1,5 +1,8 @@
 mod edge_constraint;
 pub use edge_constraint::*;
 
+mod bend_constraint;
+pub use bend_constraint::*;
+
 mod plugin;
-pub use plugin::*;
\ No newline at end of file
+pub use plugin::*;
@@ -1,13 +1,14 @@
 use aery::prelude::*;
 use bevy::{prelude::*, utils::HashSet};
 
-use crate::{EdgeConstraint, ParticlePosition};
+use crate::{BendConstraint, EdgeConstraint, ParticlePosition};
 
 pub struct ConstrainsPlugin;
 impl Plugin for ConstrainsPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<EdgeConstraint>()
-            .add_systems(Update, relax_constraints);
+            .register_type::<BendConstraint>()
+            .add_systems(Update, (relax_constraints, relax_bend_constraints).chain());
     }
 }
 
@@ -21,77 +22,95 @@ pub struct P0;
 #[derive(Relation)]
 pub struct P1;
 
+/// The third particle of a [`BendConstraint`], after the joint at `P1`.
+#[derive(Relation)]
+pub struct P2;
+
+#[allow(clippy::type_complexity)]
 fn relax_constraints(
-    constraints: Query<(
+    constraints: Query<((Entity, &EdgeConstraint), Relations<(P0, P1)>)>,
+    // The constraints linked to others. A chain of a single segment has no links, so it is
+    // only in `constraints`.
+    linked_constraints: Query<(
         (Entity, &EdgeConstraint),
         Relations<(P0, P1, ConstraintToConstraint)>,
     )>,
     mut particles: Query<&mut ParticlePosition>,
     particle_entities: Query<Entity, With<ParticlePosition>>,
 ) {
-    println!("Relaxing constraints");
-    let mut max_stress_constraint = (Entity::PLACEHOLDER, 0.0);
+    let mut stresses = Vec::new();
     for (constraint, edges) in &constraints {
-        println!("Constraint: {:?}", constraint);
         edges
             .join::<Up<P0>>(&particles)
             .join::<Up<P1>>(&particles)
             .for_each(|(a, b)| {
-                let stress = constraint.1.compute_stress(a, b);
-                println!("Stress ({:?}->{:?}): {}", a, b, stress);
-                if max_stress_constraint.0 == Entity::PLACEHOLDER
-                    || stress > max_stress_constraint.1
-                {
-                    max_stress_constraint = (constraint.0, stress);
-                }
+                stresses.push((constraint.0, constraint.1.compute_stress(a, b)));
             });
     }
-    if max_stress_constraint.0 == Entity::PLACEHOLDER {
-        return;
-    }
-    // Traverse the graph and relax the constraints strating from the most stressed one
+    // Plants, and the crowns of a plant, are separate graphs, so every graph is relaxed in
+    // turn, starting from its most stressed constraint.
+    stresses.sort_by(|(_, a), (_, b)| b.total_cmp(a));
     let mut solved_particles = HashSet::<Entity>::new();
     let mut solved_constrains = HashSet::<Entity>::new();
+    for (start, _) in stresses {
+        if solved_constrains.contains(&start) {
+            continue;
+        }
+        {
+            // TODO: move this into a function or something
+            let ((constraint_id, constraint), edges) = constraints.get(start).unwrap();
+            edges
+                .join::<Up<P0>>(&particle_entities)
+                .join::<Up<P1>>(&particle_entities)
+                .for_each(|(a, b)| {
+                    solved_particles.insert(a);
+                    solved_particles.insert(b);
+                    let [mut a, mut b] = particles.get_many_mut([a, b]).unwrap();
+                    constraint.solve(&mut a, &mut b, 0.0);
+                });
+            solved_constrains.insert(constraint_id);
+        }
+        linked_constraints
+            .traverse::<ConstraintToConstraint>([start])
+            .for_each(|(constraint_id, constraint), edges| {
+                if solved_constrains.contains(constraint_id) {
+                    TCF::Close
+                } else {
+                    edges
+                        .join::<Up<P0>>(&particle_entities)
+                        .join::<Up<P1>>(&particle_entities)
+                        .for_each(|(a, b)| {
+                            // TODO lock solved particles
+                            solved_particles.insert(a);
+                            solved_particles.insert(b);
+                            let [mut a, mut b] = particles.get_many_mut([a, b]).unwrap();
+                            constraint.solve(&mut a, &mut b, 0.0);
+                        });
+                    solved_constrains.insert(*constraint_id);
+                    TCF::Continue
+                }
+            });
+    }
+}
 
-    {
-        // TODO: move this into a function or something
-        let ((constraint_id, constraint), edges) =
-            constraints.get(max_stress_constraint.0).unwrap();
+#[allow(clippy::type_complexity)]
+fn relax_bend_constraints(
+    constraints: Query<(&BendConstraint, Relations<(P0, P1, P2)>)>,
+    particle_entities: Query<Entity, With<ParticlePosition>>,
+    mut particles: Query<&mut ParticlePosition>,
+) {
+    for (constraint, edges) in &constraints {
         edges
             .join::<Up<P0>>(&particle_entities)
             .join::<Up<P1>>(&particle_entities)
-            .for_each(|(a, b)| {
-                solved_particles.insert(a);
-                solved_particles.insert(b);
-                let [mut a, mut b] = particles.get_many_mut([a, b]).unwrap();
-                println!("Solving constraint {:?}", constraint);
-                constraint.solve(&mut a, &mut b, 0.0);
+            .join::<Up<P2>>(&particle_entities)
+            .for_each(|(a, b, c)| {
+                let Ok([mut a, b, mut c]) = particles.get_many_mut([a, b, c]) else {
+                    return;
+                };
+                constraint.solve(&mut a, &b, &mut c);
             });
-        solved_constrains.insert(constraint_id);
     }
-    constraints
-        .traverse::<ConstraintToConstraint>([max_stress_constraint.0])
-        .for_each(|(constraint_id, constraint), edges| {
-            if solved_constrains.contains(constraint_id) {
-                println!("Constraint {:?} already solved", constraint_id);
-                TCF::Close
-            } else {
-                println!("Solving constraint {:?}", constraint_id);
-                edges
-                    .join::<Up<P0>>(&particle_entities)
-                    .join::<Up<P1>>(&particle_entities)
-                    .for_each(|(a, b)| {
-                        // TODO lock solved particles
-                        solved_particles.insert(a);
-                        solved_particles.insert(b);
-                        let [mut a, mut b] = particles.get_many_mut([a, b]).unwrap();
-                        println!("Solving constraint {:?}", constraint);
-                        constraint.solve(&mut a, &mut b, 0.0);
-                    });
-                solved_constrains.insert(*constraint_id);
-                TCF::Continue
-            }
-        });
 }
 
 #[cfg(test)]
@@ -141,4 +160,24 @@ mod tests {
             });
         assert!(1 == 2);
     }
+
+    #[test]
+    fn test_every_constraint_graph_relaxes() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, Aery, ConstrainsPlugin));
+        let pairs = [1.5, 3.0].map(|x| {
+            let a = app.world.spawn(ParticlePosition(Vec3::X * x)).id();
+            let b = app.world.spawn(ParticlePosition(Vec3::new(x, x, 0.0))).id();
+            app.world
+                .spawn(EdgeConstraint::from_rest_length(1.0))
+                .set::<P0>(a)
+                .set::<P1>(b);
+            (a, b)
+        });
+        app.world.run_system_once(relax_constraints);
+        for (a, b) in pairs {
+            let position = |entity| app.world.get::<ParticlePosition>(entity).unwrap().0;
+            assert!((position(a).distance(position(b)) - 1.0).abs() < 1e-5);
+        }
+    }
 }
@@ -0,0 +1,486 @@
+use bevy::prelude::*;
+
+use crate::{
+    FlowerSpec, FruitSpec, GrowthSpec, LeafSpec, LeafletOutline, LeafletShape, OrganSpec,
+    PlantSpec, RosetteSpec, RunnerSpec, SizeDistribution, TrussSpec,
+};
+
+/// A named preset for the [`PlantSpec`] of a [`StrawberryPlant`](crate::StrawberryPlant).
+///
+/// Put it on the plant entity instead of a `PlantSpec` to grow that type of plant; every such
+/// plant is drawn with a seed of its own. To vary a preset, start from [`Cultivar::spec`] and
+/// override fields, e.g. the seed.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum Cultivar {
+    /// Flowers and fruits continuously through the season, with fruits at every stage on the
+    /// plant and few runners.
+    DayNeutral,
+    /// A large plant that crops once, with big round conical fruits ripening together and many
+    /// runners after the harvest.
+    JuneBearing,
+    /// A small, multi-crown pot plant with short trusses and no runners.
+    CompactEverbearing,
+    /// *Fragaria vesca*: thin serrated leaves, long trusses held above them, small seedy fruits
+    /// and long runners.
+    WildWoodland,
+}
+
+impl Cultivar {
+    pub const ALL: [Self; 4] = [
+        Self::DayNeutral,
+        Self::JuneBearing,
+        Self::CompactEverbearing,
+        Self::WildWoodland,
+    ];
+
+    pub fn spec(&self) -> PlantSpec {
+        match self {
+            Self::DayNeutral => day_neutral(),
+            Self::JuneBearing => june_bearing(),
+            Self::CompactEverbearing => compact_everbearing(),
+            Self::WildWoodland => wild_woodland(),
+        }
+    }
+}
+impl From<Cultivar> for PlantSpec {
+    fn from(cultivar: Cultivar) -> Self {
+        cultivar.spec()
+    }
+}
+
+fn day_neutral() -> PlantSpec {
+    let default = PlantSpec::default();
+    PlantSpec {
+        rosette: RosetteSpec {
+            truss_elevation: SizeDistribution::new(0.5, 0.15),
+            ..default.rosette
+        },
+        petiole: OrganSpec {
+            length: SizeDistribution::new(1.8, 0.3),
+            ..default.petiole
+        },
+        leaflet: OrganSpec {
+            length: SizeDistribution::new(0.75, 0.1),
+            ..default.leaflet
+        },
+        leaf: LeafSpec {
+            hue: SizeDistribution::new(0.0, 4.0),
+            lightness: SizeDistribution::new(1.0, 0.08),
+            ..default.leaf
+        },
+        truss: OrganSpec {
+            count: SizeDistribution::new(3.0, 1.0),
+            length: SizeDistribution::new(1.1, 0.3),
+            ..default.truss
+        },
+        inflorescence: TrussSpec {
+            maturity_step: 0.3,
+            ..default.inflorescence
+        },
+        fruit: FruitSpec {
+            length: SizeDistribution::new(0.38, 0.06),
+            width: SizeDistribution::new(0.8, 0.08),
+            shoulder: SizeDistribution::new(0.28, 0.05),
+            taper: SizeDistribution::new(1.6, 0.2),
+            achenes: SizeDistribution::new(190.0, 30.0),
+            hue: SizeDistribution::new(0.0, 3.0),
+            // Every stage from flower to overripe fruit is on the plant at once.
+            maturity: SizeDistribution::new(0.45, 0.55),
+            ..default.fruit
+        },
+        runner: RunnerSpec {
+            count: SizeDistribution::new(0.5, 0.5),
+            internode: OrganSpec {
+                length: SizeDistribution::new(2.2, 0.4),
+                ..default.runner.internode
+            },
+            daughters: 1,
+            ..default.runner
+        },
+        growth: GrowthSpec {
+            flowering_day: 30.0,
+            truss_interval: 8.0,
+            ripening_days: 32.0,
+            runner_day: 60.0,
+            ..default.growth
+        },
+        ..default
+    }
+}
+
+fn june_bearing() -> PlantSpec {
+    let default = PlantSpec::default();
+    PlantSpec {
+        crown: OrganSpec {
+            count: SizeDistribution::new(2.0, 1.0),
+            length: SizeDistribution::new(0.25, 0.05),
+            size: SizeDistribution::new(0.18, 0.02),
+            ..default.crown
+        },
+        rosette: RosetteSpec {
+            petiole_elevation: SizeDistribution::new(0.55, 0.15),
+            truss_elevation: SizeDistribution::new(0.6, 0.15),
+            crown_elevation: SizeDistribution::new(0.4, 0.1),
+            ..default.rosette
+        },
+        petiole: OrganSpec {
+            count: SizeDistribution::new(6.0, 1.0),
+            length: SizeDistribution::new(2.3, 0.4),
+            size: SizeDistribution::new(0.035, 0.005),
+            ..default.petiole
+        },
+        leaflet: OrganSpec {
+            length: SizeDistribution::new(0.95, 0.15),
+            size: SizeDistribution::constant(0.012),
+            ..default.leaflet
+        },
+        leaf: LeafSpec {
+            lateral_angle: 0.95,
+            bend: 1.05,
+            shape: LeafletShape {
+                outline: LeafletOutline::Obovate,
+                width: 0.8,
+                serrations: 10,
+                serration_depth: 0.1,
+                fold: 0.2,
+                veins: 9,
+                vein_depth: 0.01,
+                color: Color::rgb(0.11, 0.36, 0.09),
+            },
+            hue: SizeDistribution::new(0.0, 3.0),
+            lightness: SizeDistribution::new(0.95, 0.06),
+        },
+        truss: OrganSpec {
+            length: SizeDistribution::new(1.3, 0.3),
+            size: SizeDistribution::new(0.03, 0.005),
+            rotation: SizeDistribution::new(0.2, 0.1),
+            ..default.truss
+        },
+        inflorescence: TrussSpec {
+            orders: 4,
+            branch_angle: SizeDistribution::new(0.45, 0.1),
+            internode: OrganSpec {
+                length: SizeDistribution::new(0.45, 0.1),
+                size: SizeDistribution::new(0.02, 0.003),
+                ..default.inflorescence.internode
+            },
+            pedicel: OrganSpec {
+                length: SizeDistribution::new(0.4, 0.08),
+                size: SizeDistribution::new(0.014, 0.002),
+                ..default.inflorescence.pedicel
+            },
+            length_decay: 0.7,
+            maturity_step: 0.15,
+            ..default.inflorescence
+        },
+        flower: FlowerSpec {
+            petals: SizeDistribution::new(5.5, 0.5),
+            petal_length: SizeDistribution::new(0.14, 0.02),
+            petal_width: SizeDistribution::new(0.95, 0.1),
+            receptacle_radius: SizeDistribution::new(0.04, 0.005),
+            stamens: SizeDistribution::new(24.0, 4.0),
+            ..default.flower
+        },
+        fruit: FruitSpec {
+            length: SizeDistribution::new(0.42, 0.08),
+            width: SizeDistribution::new(0.95, 0.08),
+            shoulder: SizeDistribution::new(0.35, 0.05),
+            taper: SizeDistribution::new(1.1, 0.2),
+            achenes: SizeDistribution::new(220.0, 40.0),
+            sepals: SizeDistribution::new(11.0, 1.0),
+            sepal_length: SizeDistribution::new(0.4, 0.1),
+            darkness: SizeDistribution::new(0.2, 0.1),
+            hue: SizeDistribution::new(-4.0, 3.0),
+            // The whole crop ripens within a few weeks.
+            maturity: SizeDistribution::new(0.6, 0.25),
+            ..default.fruit
+        },
+        runner: RunnerSpec {
+            count: SizeDistribution::new(3.0, 1.0),
+            internode: OrganSpec {
+                size: SizeDistribution::new(0.017, 0.003),
+                ..default.runner.internode
+            },
+            daughters: 3,
+            ..default.runner
+        },
+        growth: GrowthSpec {
+            elongation_days: 14.0,
+            plastochron: 8.0,
+            flowering_day: 40.0,
+            truss_interval: 4.0,
+            order_days: 3.0,
+            ripening_days: 30.0,
+            runner_day: 80.0,
+            ..default.growth
+        },
+        ..default
+    }
+}
+
+fn compact_everbearing() -> PlantSpec {
+    let default = PlantSpec::default();
+    PlantSpec {
+        crown: OrganSpec {
+            count: SizeDistribution::new(2.0, 1.0),
+            length: SizeDistribution::new(0.15, 0.03),
+            size: SizeDistribution::new(0.12, 0.02),
+            ..default.crown
+        },
+        rosette: RosetteSpec {
+            petiole_elevation: SizeDistribution::new(0.7, 0.15),
+            truss_elevation: SizeDistribution::new(0.8, 0.15),
+            crown_elevation: SizeDistribution::new(0.6, 0.1),
+            ..default.rosette
+        },
+        petiole: OrganSpec {
+            count: SizeDistribution::new(4.0, 1.0),
+            segments: 3,
+            length: SizeDistribution::new(1.2, 0.2),
+            size: SizeDistribution::new(0.025, 0.004),
+            ..default.petiole
+        },
+        leaflet: OrganSpec {
+            length: SizeDistribution::new(0.6, 0.1),
+            size: SizeDistribution::constant(0.009),
+            ..default.leaflet
+        },
+        leaf: LeafSpec {
+            lateral_angle: 0.85,
+            bend: 0.9,
+            shape: LeafletShape {
+                outline: LeafletOutline::Elliptic,
+                width: 0.65,
+                serrations: 8,
+                fold: 0.25,
+                veins: 7,
+                color: Color::rgb(0.1, 0.33, 0.08),
+                ..default.leaf.shape
+            },
+            // Pot plants are grown uniform.
+            hue: SizeDistribution::new(0.0, 2.0),
+            lightness: SizeDistribution::new(1.0, 0.04),
+        },
+        truss: OrganSpec {
+            count: SizeDistribution::new(3.0, 1.0),
+            segments: 2,
+            length: SizeDistribution::new(0.8, 0.2),
+            size: SizeDistribution::new(0.022, 0.004),
+            ..default.truss
+        },
+        inflorescence: TrussSpec {
+            branches: SizeDistribution::new(1.5, 0.5),
+            branch_angle: SizeDistribution::new(0.55, 0.1),
+            internode: OrganSpec {
+                length: SizeDistribution::new(0.3, 0.08),
+                size: SizeDistribution::new(0.016, 0.003),
+                ..default.inflorescence.internode
+            },
+            pedicel: OrganSpec {
+                length: SizeDistribution::new(0.3, 0.06),
+                size: SizeDistribution::new(0.011, 0.002),
+                ..default.inflorescence.pedicel
+            },
+            length_decay: 0.8,
+            maturity_step: 0.3,
+            ..default.inflorescence
+        },
+        flower: FlowerSpec {
+            petal_length: SizeDistribution::new(0.1, 0.015),
+            cup: SizeDistribution::new(0.2, 0.05),
+            receptacle_radius: SizeDistribution::new(0.03, 0.004),
+            stamens: SizeDistribution::new(18.0, 3.0),
+            ..default.flower
+        },
+        fruit: FruitSpec {
+            length: SizeDistribution::new(0.28, 0.05),
+            width: SizeDistribution::new(0.8, 0.08),
+            taper: SizeDistribution::new(1.5, 0.25),
+            achenes: SizeDistribution::new(150.0, 30.0),
+            sepal_length: SizeDistribution::new(0.5, 0.1),
+            hue: SizeDistribution::new(3.0, 2.0),
+            maturity: SizeDistribution::new(0.4, 0.5),
+            ..default.fruit
+        },
+        runner: RunnerSpec {
+            count: SizeDistribution::constant(0.0),
+            daughters: 0,
+            ..default.runner
+        },
+        growth: GrowthSpec {
+            elongation_days: 10.0,
+            plastochron: 6.0,
+            flowering_day: 25.0,
+            truss_interval: 7.0,
+            ripening_days: 30.0,
+            ..default.growth
+        },
+        ..default
+    }
+}
+
+fn wild_woodland() -> PlantSpec {
+    let default = PlantSpec::default();
+    PlantSpec {
+        crown: OrganSpec {
+            length: SizeDistribution::new(0.12, 0.03),
+            size: SizeDistribution::new(0.08, 0.01),
+            ..default.crown
+        },
+        rosette: RosetteSpec {
+            petiole_elevation: SizeDistribution::new(0.45, 0.15),
+            truss_elevation: SizeDistribution::new(0.3, 0.1),
+            ..default.rosette
+        },
+        petiole: OrganSpec {
+            length: SizeDistribution::new(1.0, 0.25),
+            size: SizeDistribution::new(0.015, 0.003),
+            rotation: SizeDistribution::new(0.15, 0.1),
+            ..default.petiole
+        },
+        leaflet: OrganSpec {
+            length: SizeDistribution::new(0.45, 0.08),
+            size: SizeDistribution::constant(0.006),
+            rotation: SizeDistribution::new(0.08, 0.05),
+            ..default.leaflet
+        },
+        leaf: LeafSpec {
+            lateral_angle: 1.0,
+            bend: 1.1,
+            shape: LeafletShape {
+                outline: LeafletOutline::Elliptic,
+                width: 0.6,
+                serrations: 12,
+                serration_depth: 0.18,
+                fold: 0.1,
+                veins: 11,
+                vein_depth: 0.012,
+                color: Color::rgb(0.18, 0.45, 0.12),
+            },
+            // From yellowish leaves in the sun to dark ones in the shade.
+            hue: SizeDistribution::new(4.0, 6.0),
+            lightness: SizeDistribution::new(1.0, 0.15),
+        },
+        // Trusses outgrow the leaves and hold the fruits above them.
+        truss: OrganSpec {
+            length: SizeDistribution::new(1.4, 0.3),
+            size: SizeDistribution::new(0.012, 0.002),
+            rotation: SizeDistribution::new(0.2, 0.1),
+            ..default.truss
+        },
+        inflorescence: TrussSpec {
+            branch_angle: SizeDistribution::new(0.6, 0.15),
+            internode: OrganSpec {
+                length: SizeDistribution::new(0.3, 0.08),
+                size: SizeDistribution::new(0.008, 0.001),
+                rotation: SizeDistribution::new(0.15, 0.05),
+                ..default.inflorescence.internode
+            },
+            pedicel: OrganSpec {
+                length: SizeDistribution::new(0.3, 0.06),
+                size: SizeDistribution::new(0.006, 0.001),
+                ..default.inflorescence.pedicel
+            },
+            length_decay: 0.8,
+            maturity_step: 0.3,
+            ..default.inflorescence
+        },
+        flower: FlowerSpec {
+            petal_length: SizeDistribution::new(0.07, 0.01),
+            petal_width: SizeDistribution::new(0.85, 0.1),
+            cup: SizeDistribution::new(0.1, 0.05),
+            receptacle_radius: SizeDistribution::new(0.02, 0.003),
+            stamens: SizeDistribution::new(20.0, 2.0),
+            stamen_length: SizeDistribution::new(0.8, 0.1),
+            ..default.flower
+        },
+        fruit: FruitSpec {
+            length: SizeDistribution::new(0.12, 0.03),
+            width: SizeDistribution::new(0.8, 0.1),
+            shoulder: SizeDistribution::new(0.35, 0.05),
+            taper: SizeDistribution::new(1.8, 0.3),
+            achenes: SizeDistribution::new(80.0, 20.0),
+            sepals: SizeDistribution::constant(10.0),
+            sepal_length: SizeDistribution::new(0.7, 0.1),
+            darkness: SizeDistribution::new(0.25, 0.1),
+            hue: SizeDistribution::new(-6.0, 4.0),
+            ..default.fruit
+        },
+        runner: RunnerSpec {
+            count: SizeDistribution::new(3.0, 1.0),
+            internode: OrganSpec {
+                length: SizeDistribution::new(1.6, 0.4),
+                size: SizeDistribution::new(0.008, 0.002),
+                rotation: SizeDistribution::new(0.06, 0.04),
+                ..default.runner.internode
+            },
+            elevation: SizeDistribution::new(1.4, 0.08),
+            daughters: 3,
+            daughter_scale: 0.7,
+        },
+        growth: GrowthSpec {
+            elongation_days: 10.0,
+            plastochron: 6.0,
+            flowering_day: 30.0,
+            truss_interval: 8.0,
+            order_days: 5.0,
+            fruit_set_days: 4.0,
+            ripening_days: 25.0,
+            runner_day: 40.0,
+        },
+        ..default
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_utils::{spawn_plant, test_app},
+        Fruit, Leaflet, Organ,
+    };
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn mean_fruit_length(cultivar: Cultivar) -> f32 {
+        let mut app = test_app();
+        spawn_plant(&mut app, cultivar);
+        app.world.run_system_once(|fruits: Query<&Fruit>| {
+            let lengths: Vec<f32> = fruits.iter().map(|fruit| fruit.shape.length).collect();
+            assert!(!lengths.is_empty());
+            lengths.iter().sum::<f32>() / lengths.len() as f32
+        })
+    }
+
+    #[test]
+    fn test_wild_fruits_are_smaller() {
+        let wild = mean_fruit_length(Cultivar::WildWoodland);
+        let june = mean_fruit_length(Cultivar::JuneBearing);
+        assert!(wild < 0.5 * june);
+    }
+
+    #[test]
+    fn test_plants_of_a_cultivar_differ() {
+        let mut app = test_app();
+        let a = spawn_plant(&mut app, Cultivar::DayNeutral);
+        let b = spawn_plant(&mut app, Cultivar::DayNeutral);
+        let colors = app
+            .world
+            .run_system_once(|leaflets: Query<(&Leaflet, &Organ)>| {
+                leaflets
+                    .iter()
+                    .map(|(leaflet, organ)| (organ.plant, leaflet.shape.color))
+                    .collect::<Vec<_>>()
+            });
+        let colors_of = |plant| {
+            (colors.iter())
+                .filter(|(owner, _)| *owner == plant)
+                .map(|(_, color)| *color)
+                .collect::<Vec<_>>()
+        };
+        let (a, b) = (colors_of(a), colors_of(b));
+        assert!(!a.is_empty() && !b.is_empty());
+        assert_ne!(a, b);
+        // The leaves of one plant vary in color too.
+        assert!(a.iter().any(|color| *color != a[0]));
+    }
+}
@@ -0,0 +1,599 @@
+use std::{
+    f32::consts::{PI, TAU},
+    str::FromStr,
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use iter_tools::Itertools;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{parse_name, value_noise, MeshMap, PlantingPattern, SceneLayout, SizeDistribution};
+
+/// A prop of the cultivation environment. [`EnvironmentSpec::generate`] makes a mesh of each,
+/// with every face labelled with its prop.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+pub enum Prop {
+    /// The ground, raised beds and the substrate in gutters.
+    Soil,
+    Mulch,
+    Straw,
+    /// Gutters and the stands they rest on.
+    Gutter,
+    Wire,
+}
+impl Prop {
+    pub const ALL: [Prop; 5] = [
+        Prop::Soil,
+        Prop::Mulch,
+        Prop::Straw,
+        Prop::Gutter,
+        Prop::Wire,
+    ];
+
+    /// An empty mesh whose faces will show this prop.
+    fn mesh(self) -> MeshMap {
+        let mut mesh = MeshMap::default();
+        mesh.set_label(Some(self.into()));
+        mesh
+    }
+}
+/// Parses the name of a prop in an OBJ group, like `Mulch`, in any case.
+impl FromStr for Prop {
+    type Err = String;
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        parse_name(&Self::ALL, name).ok_or_else(|| format!("unknown prop `{name}`"))
+    }
+}
+
+/// Ground under the plants, a heightfield with raised beds, noise and clods.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct SoilSpec {
+    /// Size of a heightfield cell.
+    pub resolution: f32,
+    /// Height of the noise on the surface.
+    pub roughness: f32,
+    /// Number of clods per unit of ground area.
+    pub clods: f32,
+    /// Radius of a clod.
+    pub clod_size: SizeDistribution,
+    pub color: Color,
+}
+impl Default for SoilSpec {
+    fn default() -> Self {
+        Self {
+            resolution: 0.25,
+            roughness: 0.08,
+            clods: 0.5,
+            clod_size: SizeDistribution::new(0.15, 0.08),
+            color: Color::rgb(0.3, 0.2, 0.12),
+        }
+    }
+}
+
+/// Plastic film covering the raised beds, with a planting hole around every plant.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct MulchSpec {
+    pub color: Color,
+    pub hole_radius: f32,
+    /// Height of the wrinkles in the film.
+    pub wrinkle: f32,
+    /// How far the film reaches past the bed top on either side, down its shoulders.
+    pub overhang: f32,
+}
+impl Default for MulchSpec {
+    fn default() -> Self {
+        Self {
+            color: Color::rgb(0.04, 0.04, 0.05),
+            hole_radius: 0.6,
+            wrinkle: 0.03,
+            overhang: 0.6,
+        }
+    }
+}
+
+/// Loose straw on the ground, kept off the mulch.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct StrawSpec {
+    /// Number of straws per unit of ground area.
+    pub density: f32,
+    pub length: SizeDistribution,
+    pub width: f32,
+    pub color: Color,
+}
+impl Default for StrawSpec {
+    fn default() -> Self {
+        Self {
+            density: 8.0,
+            length: SizeDistribution::new(0.6, 0.3),
+            width: 0.03,
+            color: Color::rgb(0.8, 0.68, 0.35),
+        }
+    }
+}
+
+/// Troughs of a tabletop system, filled with substrate up to the plant bases.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct GutterSpec {
+    pub depth: f32,
+    /// Height of the rim above the substrate.
+    pub rim: f32,
+    /// Distance between the legs of the stands.
+    pub leg_spacing: f32,
+    pub leg_radius: f32,
+    pub color: Color,
+    pub substrate_color: Color,
+}
+impl Default for GutterSpec {
+    fn default() -> Self {
+        Self {
+            depth: 0.8,
+            rim: 0.15,
+            leg_spacing: 6.0,
+            leg_radius: 0.06,
+            color: Color::rgb(0.85, 0.85, 0.82),
+            substrate_color: Color::rgb(0.2, 0.13, 0.08),
+        }
+    }
+}
+
+/// Support wires strung along both sides of every bed or gutter.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct WireSpec {
+    /// Heights of the wires above the bed or gutter top.
+    pub heights: Vec<f32>,
+    pub radius: f32,
+    /// Distance between the posts the wires are tied to.
+    pub post_spacing: f32,
+    /// How far a wire sags between two posts.
+    pub sag: f32,
+    pub color: Color,
+}
+impl Default for WireSpec {
+    fn default() -> Self {
+        Self {
+            heights: vec![1.0],
+            radius: 0.015,
+            post_spacing: 8.0,
+            sag: 0.1,
+            color: Color::rgb(0.6, 0.6, 0.62),
+        }
+    }
+}
+
+/// Generates the cultivation environment around the plants of a [`SceneLayout`].
+#[derive(Resource, Debug, Clone, PartialEq, Reflect)]
+pub struct EnvironmentSpec {
+    pub soil: SoilSpec,
+    /// Mulch on raised beds.
+    pub mulch: Option<MulchSpec>,
+    pub straw: Option<StrawSpec>,
+    /// Gutters of a tabletop layout.
+    pub gutter: GutterSpec,
+    pub wires: Option<WireSpec>,
+    pub seed: u64,
+}
+impl Default for EnvironmentSpec {
+    fn default() -> Self {
+        Self {
+            soil: SoilSpec::default(),
+            mulch: Some(MulchSpec::default()),
+            straw: Some(StrawSpec::default()),
+            gutter: GutterSpec::default(),
+            wires: None,
+            seed: 0,
+        }
+    }
+}
+
+/// Clods, each a centre and radius, bucketed into square cells as large as the largest clod,
+/// so a point only has to look at the clods of its own and the neighbouring cells.
+struct ClodGrid {
+    cell: f32,
+    cells: HashMap<IVec2, Vec<(Vec2, f32)>>,
+}
+impl ClodGrid {
+    fn new(clods: Vec<(Vec2, f32)>) -> Self {
+        let cell = clods.iter().map(|(_, radius)| *radius).fold(0.01, f32::max);
+        let mut cells = HashMap::<IVec2, Vec<(Vec2, f32)>>::new();
+        for (center, radius) in clods {
+            let key = (center / cell).floor().as_ivec2();
+            cells.entry(key).or_default().push((center, radius));
+        }
+        Self { cell, cells }
+    }
+
+    /// Height of the highest clod over `point`.
+    fn height(&self, point: Vec2) -> f32 {
+        let key = (point / self.cell).floor().as_ivec2();
+        (-1..=1)
+            .cartesian_product(-1..=1)
+            .filter_map(|(x, y)| self.cells.get(&(key + IVec2::new(x, y))))
+            .flatten()
+            .map(|(center, radius)| {
+                let d = point.distance(*center) / radius;
+                0.6 * radius * (1.0 - d * d).max(0.0).sqrt()
+            })
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Ground heights: raised beds with sloping shoulders, surface noise and clods.
+struct Terrain {
+    beds: Vec<(Rect, f32)>,
+    clods: ClodGrid,
+    roughness: f32,
+    seed: u32,
+}
+impl Terrain {
+    fn height(&self, point: Vec2) -> f32 {
+        let bed = self
+            .beds
+            .iter()
+            .map(|(rect, height)| {
+                let outside = (rect.min - point).max(point - rect.max).max(Vec2::ZERO);
+                let shoulder = height.max(0.1);
+                height * (1.0 - outside.length() / shoulder).clamp(0.0, 1.0)
+            })
+            .fold(0.0, f32::max);
+        let noise = value_noise(point * 1.5, self.seed) + 0.5 * value_noise(point * 4.0, self.seed);
+        bed + self.roughness * (noise / 1.5 - 0.5) + self.clods.height(point)
+    }
+}
+
+impl EnvironmentSpec {
+    /// Generates the props around the plants of `layout`, one mesh for each kind of prop.
+    pub fn generate(&self, layout: &SceneLayout) -> Vec<(Prop, MeshMap)> {
+        let rng = &mut ChaCha8Rng::seed_from_u64(self.seed);
+        let ground = Rect::from_center_size(Vec2::ZERO, layout.ground_size());
+        let footprints = layout.footprints();
+        let plants = layout
+            .placements()
+            .iter()
+            .map(|placement| placement.translation.xz())
+            .collect_vec();
+        let raised_beds = matches!(layout.pattern, PlantingPattern::RaisedBeds { .. });
+        let gutters = matches!(layout.pattern, PlantingPattern::TabletopGutters { .. });
+
+        let clods = (self.soil.clods * ground.width() * ground.height()) as usize;
+        let terrain = Terrain {
+            beds: if raised_beds {
+                footprints.clone()
+            } else {
+                Vec::new()
+            },
+            clods: ClodGrid::new(
+                (0..clods)
+                    .map(|_| {
+                        let center = Vec2::new(
+                            rng.gen_range(ground.min.x..=ground.max.x),
+                            rng.gen_range(ground.min.y..=ground.max.y),
+                        );
+                        (center, self.soil.clod_size.sample(rng).max(0.01))
+                    })
+                    .collect(),
+            ),
+            roughness: self.soil.roughness,
+            seed: rng.gen(),
+        };
+
+        let mut soil = Prop::Soil.mesh();
+        let soil_color = Vec4::from(self.soil.color.as_linear_rgba_f32());
+        add_heightfield(
+            &mut soil,
+            ground,
+            self.soil.resolution,
+            |point| point.extend(terrain.height(point)).xzy(),
+            |point| {
+                let moisture = 0.7 + 0.5 * value_noise(point * 0.8, terrain.seed.wrapping_add(1));
+                (soil_color.truncate() * moisture).extend(1.0).into()
+            },
+            |_| true,
+        );
+        let mut props = Vec::new();
+
+        let mulch = match &self.mulch {
+            Some(mulch) if raised_beds => {
+                let mut mesh = Prop::Mulch.mesh();
+                let color = mulch.color.as_linear_rgba_f32();
+                let seed = rng.gen();
+                for (rect, _) in &footprints {
+                    let rect = Rect::from_center_size(
+                        rect.center(),
+                        rect.size() + Vec2::new(2.0 * mulch.overhang, 0.0),
+                    );
+                    let resolution = self.soil.resolution.min(mulch.hole_radius / 2.0);
+                    add_heightfield(
+                        &mut mesh,
+                        rect,
+                        resolution,
+                        |point| {
+                            let wrinkle = mulch.wrinkle * value_noise(point * 3.0, seed);
+                            point.extend(terrain.height(point) + wrinkle + 0.02).xzy()
+                        },
+                        |_| color,
+                        |point| {
+                            plants
+                                .iter()
+                                .all(|plant| plant.distance(point) > mulch.hole_radius)
+                        },
+                    );
+                }
+                props.push((Prop::Mulch, mesh));
+                Some(mulch)
+            }
+            _ => None,
+        };
+
+        if let Some(straw) = &self.straw {
+            let mut mesh = Prop::Straw.mesh();
+            let color = Vec4::from(straw.color.as_linear_rgba_f32());
+            let count = (straw.density * ground.width() * ground.height()).min(50_000.0) as usize;
+            for _ in 0..count {
+                let center = Vec2::new(
+                    rng.gen_range(ground.min.x..=ground.max.x),
+                    rng.gen_range(ground.min.y..=ground.max.y),
+                );
+                let on_mulch = mulch.is_some_and(|mulch| {
+                    footprints.iter().any(|(rect, _)| {
+                        (center.x - rect.center().x).abs() < rect.width() / 2.0 + mulch.overhang
+                            && rect.contains(Vec2::new(rect.center().x, center.y))
+                    })
+                });
+                if on_mulch {
+                    continue;
+                }
+                let length = straw.length.sample(rng).max(0.05);
+                let direction = Vec2::from_angle(rng.gen_range(0.0..TAU));
+                let shade = rng.gen_range(0.7..1.1);
+                let color: [f32; 4] = (color.truncate() * shade).extend(1.0).into();
+                let points = (0..=2)
+                    .map(|i| {
+                        let point = center + direction * length * (i as f32 / 2.0 - 0.5);
+                        point.extend(terrain.height(point) + 0.01).xzy()
+                    })
+                    .collect_vec();
+                add_strip(&mut mesh, &points, straw.width, color);
+            }
+            props.push((Prop::Straw, mesh));
+        }
+
+        if gutters {
+            let gutter = &self.gutter;
+            let mut mesh = Prop::Gutter.mesh();
+            let color = gutter.color.as_linear_rgba_f32();
+            for (rect, height) in &footprints {
+                let rim = height + gutter.rim;
+                let bottom = rim - gutter.depth;
+                let profile = [
+                    Vec2::new(rect.min.x, rim),
+                    Vec2::new(rect.min.x, bottom),
+                    Vec2::new(rect.max.x, bottom),
+                    Vec2::new(rect.max.x, rim),
+                ];
+                let rows = [rect.min.y, rect.max.y].map(|z| {
+                    profile.map(|point| {
+                        let vertex = mesh.add_vertex([point.x, point.y, z]);
+                        mesh.set_color(vertex, color);
+                        vertex
+                    })
+                });
+                for i in 0..profile.len() - 1 {
+                    mesh.add_face((rows[0][i], rows[1][i], rows[0][i + 1]));
+                    mesh.add_face((rows[0][i + 1], rows[1][i], rows[1][i + 1]));
+                }
+                let legs = (rect.height() / gutter.leg_spacing).ceil().max(1.0) as u32;
+                for l in 0..=legs {
+                    let z = rect.min.y + rect.height() * l as f32 / legs as f32;
+                    for x in [rect.min.x, rect.max.x] {
+                        let ground = terrain.height(Vec2::new(x, z)) - 0.1;
+                        let points = [Vec3::new(x, ground, z), Vec3::new(x, bottom, z)];
+                        add_tube(&mut mesh, &points, gutter.leg_radius, color);
+                    }
+                }
+                let substrate = Rect::from_center_size(rect.center(), rect.size() * 0.98);
+                let substrate_color = gutter.substrate_color.as_linear_rgba_f32();
+                let seed = rng.gen();
+                add_heightfield(
+                    &mut soil,
+                    substrate,
+                    self.soil.resolution,
+                    |point| {
+                        let lumps = self.soil.roughness * value_noise(point * 4.0, seed);
+                        point
+                            .extend(height + lumps - 0.5 * self.soil.roughness)
+                            .xzy()
+                    },
+                    |_| substrate_color,
+                    |_| true,
+                );
+            }
+            props.push((Prop::Gutter, mesh));
+        }
+
+        if let Some(wires) = &self.wires {
+            let mut mesh = Prop::Wire.mesh();
+            let color = wires.color.as_linear_rgba_f32();
+            for (rect, height) in &footprints {
+                let spans = (rect.height() / wires.post_spacing).ceil().max(1.0) as u32;
+                let segments = spans * 8;
+                for (x, wire_height) in [rect.min.x, rect.max.x]
+                    .into_iter()
+                    .cartesian_product(&wires.heights)
+                {
+                    let points = (0..=segments)
+                        .map(|i| {
+                            let s = i as f32 / segments as f32;
+                            let span = (s * spans as f32).fract();
+                            let sag = wires.sag * 4.0 * span * (1.0 - span);
+                            let z = rect.min.y + s * rect.height();
+                            Vec3::new(x, height + wire_height - sag, z)
+                        })
+                        .collect_vec();
+                    add_tube(&mut mesh, &points, wires.radius, color);
+                }
+            }
+            props.push((Prop::Wire, mesh));
+        }
+
+        props.insert(0, (Prop::Soil, soil));
+        props
+    }
+}
+
+/// Adds a grid over `rect` in the XZ plane, placing each grid point with `position`. Quads whose
+/// centre fails `keep` are left out.
+fn add_heightfield(
+    mesh: &mut MeshMap,
+    rect: Rect,
+    resolution: f32,
+    position: impl Fn(Vec2) -> Vec3,
+    color: impl Fn(Vec2) -> [f32; 4],
+    keep: impl Fn(Vec2) -> bool,
+) {
+    let columns = (rect.width() / resolution).ceil().max(1.0) as usize;
+    let rows = (rect.height() / resolution).ceil().max(1.0) as usize;
+    let point = |column: usize, row: usize| {
+        rect.min + rect.size() * Vec2::new(column as f32 / columns as f32, row as f32 / rows as f32)
+    };
+    let grid = (0..=rows)
+        .map(|row| {
+            (0..=columns)
+                .map(|column| {
+                    let point = point(column, row);
+                    let vertex = mesh.add_vertex(position(point));
+                    mesh.set_uv(vertex, (point - rect.min) / rect.size());
+                    mesh.set_color(vertex, color(point));
+                    vertex
+                })
+                .collect_vec()
+        })
+        .collect_vec();
+    for (row, (a, b)) in grid.iter().tuple_windows().enumerate() {
+        for i in 0..columns {
+            let center = (point(i, row) + point(i + 1, row + 1)) / 2.0;
+            if keep(center) {
+                mesh.add_face((a[i], b[i], a[i + 1]));
+                mesh.add_face((a[i + 1], b[i], b[i + 1]));
+            }
+        }
+    }
+}
+
+/// Adds a flat strip of `width` along `points`, facing up.
+fn add_strip(mesh: &mut MeshMap, points: &[Vec3], width: f32, color: [f32; 4]) {
+    let rows = points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            let direction = points[(i + 1).min(points.len() - 1)] - points[i.saturating_sub(1)];
+            let side = direction.cross(Vec3::Y).normalize_or_zero() * width / 2.0;
+            [*point - side, *point + side].map(|point| {
+                let vertex = mesh.add_vertex(point);
+                mesh.set_color(vertex, color);
+                vertex
+            })
+        })
+        .collect_vec();
+    for (a, b) in rows.iter().tuple_windows() {
+        mesh.add_face((a[0], b[0], a[1]));
+        mesh.add_face((a[1], b[0], b[1]));
+    }
+}
+
+/// Adds a square tube of `radius` along `points`.
+fn add_tube(mesh: &mut MeshMap, points: &[Vec3], radius: f32, color: [f32; 4]) {
+    let sides = 4;
+    let rings = points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            let direction = (points[(i + 1).min(points.len() - 1)] - points[i.saturating_sub(1)])
+                .normalize_or_zero();
+            let rotation = Quat::from_rotation_arc(Vec3::Y, direction);
+            (0..sides)
+                .map(|k| {
+                    let angle = k as f32 / sides as f32 * TAU + PI / 4.0;
+                    let offset = rotation * Vec3::new(angle.cos(), 0.0, angle.sin()) * radius;
+                    let vertex = mesh.add_vertex(*point + offset);
+                    mesh.set_color(vertex, color);
+                    vertex
+                })
+                .collect_vec()
+        })
+        .collect_vec();
+    for (a, b) in rings.iter().tuple_windows() {
+        for i in 0..sides {
+            let j = (i + 1) % sides;
+            mesh.add_face((a[i], a[j], b[i]));
+            mesh.add_face((b[i], a[j], b[j]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mulch_has_planting_holes() {
+        let layout = SceneLayout::default().with_jitter(0.0);
+        let environment = EnvironmentSpec {
+            straw: None,
+            ..default()
+        };
+        let props = environment.generate(&layout);
+        let (_, mulch) = props
+            .iter()
+            .find(|(prop, _)| *prop == Prop::Mulch)
+            .expect("raised beds are mulched");
+        let plant = layout.placements()[0].translation;
+        let covers_plant = mulch.face_iter().any(|face| {
+            let center = Vec3::from(mulch.face_center(face));
+            center.xz().distance(plant.xz()) < 0.5 * MulchSpec::default().hole_radius
+        });
+        assert!(!covers_plant);
+        assert!(props.iter().any(|(prop, _)| *prop == Prop::Soil));
+    }
+
+    #[test]
+    fn test_prop_faces_are_labelled() {
+        let environment = EnvironmentSpec {
+            wires: Some(WireSpec::default()),
+            ..default()
+        };
+        for (prop, mesh) in environment.generate(&SceneLayout::default()) {
+            assert!(mesh.face_iter().count() > 0);
+            assert!(mesh
+                .face_iter()
+                .all(|face| mesh.face_label(face) == Some(prop.into())));
+            let mut obj = Vec::new();
+            mesh.write_obj(&mut obj, &default()).unwrap();
+            let read = MeshMap::read_obj(obj.as_slice()).unwrap();
+            assert_eq!(read.face_label(0.into()), Some(prop.into()));
+        }
+    }
+
+    #[test]
+    fn test_clod_grid_finds_every_clod() {
+        let rng = &mut ChaCha8Rng::seed_from_u64(3);
+        let clods = (0..200)
+            .map(|_| {
+                let center = Vec2::new(rng.gen_range(-5.0..5.0), rng.gen_range(-5.0..5.0));
+                (center, rng.gen_range(0.05..0.4))
+            })
+            .collect_vec();
+        let grid = ClodGrid::new(clods.clone());
+        for _ in 0..500 {
+            let point = Vec2::new(rng.gen_range(-6.0..6.0), rng.gen_range(-6.0..6.0));
+            let scanned = clods
+                .iter()
+                .map(|(center, radius)| {
+                    let d = point.distance(*center) / radius;
+                    0.6 * radius * (1.0 - d * d).max(0.0).sqrt()
+                })
+                .fold(0.0, f32::max);
+            assert_eq!(grid.height(point), scanned);
+        }
+    }
+}
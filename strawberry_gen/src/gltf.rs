@@ -0,0 +1,736 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use aery::prelude::*;
+use bevy::{
+    ecs::system::{RunSystemOnce, SystemParam},
+    prelude::*,
+    utils::HashMap,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    extension, group_name, unsupported_extension, AxisUp, Dormant, FaceId, FaceLabel, MeshIoError,
+    MeshMap, Organ, OrganId, OrganKind, PlantMeshBuilder, PlantRoot, RootStem, VertexId,
+};
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+const FLOAT: u32 = 5126;
+
+/// Joint indices are written as unsigned shorts, the widest type glTF allows for them.
+const MAX_JOINTS: usize = u16::MAX as usize + 1;
+
+/// A plant as a glTF 2.0 asset: a mesh for every organ, with a PBR material per organ kind, and
+/// a skeleton with a joint for every stem, nested like the `AxisUp` stem chain. The joint of a
+/// stem sits at the base of its segment, turned like the stem, so turning it bends the stem
+/// and everything it bears. The mesh and node of a defective fruit list the kinds of its
+/// defects in `extras.defects`.
+///
+/// Positions are in the frame of the world the plant was generated in.
+pub struct PlantGltf {
+    document: Value,
+    buffer: Vec<u8>,
+}
+
+impl PlantGltf {
+    /// Exports `plant` from `world`, or `None` if it has not been generated. Fails if the plant
+    /// has more stems than glTF can index joints.
+    pub fn from_world(world: &mut World, plant: Entity) -> Result<Option<Self>, MeshIoError> {
+        world.run_system_once_with(plant, |In(plant): In<Entity>, exporter: PlantExporter| {
+            exporter.export(plant)
+        })
+    }
+
+    /// Writes the asset to `path` as `.gltf`, with the buffer embedded, or as `.glb`,
+    /// depending on its extension.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), MeshIoError> {
+        let path = path.as_ref();
+        let mut writer = BufWriter::new(File::create(path)?);
+        match extension(path).as_deref() {
+            Some("gltf") => self.write_gltf(&mut writer)?,
+            Some("glb") => self.write_glb(&mut writer)?,
+            _ => return Err(unsupported_extension(path)),
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the asset as JSON, with the buffer embedded as a data URI.
+    pub fn write_gltf(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut document = self.document.clone();
+        document["buffers"][0]["uri"] = format!(
+            "data:application/octet-stream;base64,{}",
+            base64(&self.buffer)
+        )
+        .into();
+        serde_json::to_writer(writer, &document)?;
+        Ok(())
+    }
+
+    /// Writes the asset as binary glTF.
+    pub fn write_glb(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut json = serde_json::to_vec(&self.document)?;
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut bin = self.buffer.clone();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+        let length = 12 + 8 + json.len() + 8 + bin.len();
+        writer.write_all(b"glTF")?;
+        writer.write_all(&2u32.to_le_bytes())?;
+        writer.write_all(&(length as u32).to_le_bytes())?;
+        for (kind, chunk) in [(b"JSON", &json), (b"BIN\0", &bin)] {
+            writer.write_all(&(chunk.len() as u32).to_le_bytes())?;
+            writer.write_all(kind)?;
+            writer.write_all(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+            bits | (*byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            encoded.push(if i <= chunk.len() {
+                ALPHABET[(bits >> (18 - 6 * i)) as usize & 63] as char
+            } else {
+                '='
+            });
+        }
+    }
+    encoded
+}
+
+/// The buffer of an asset with its views and accessors, one view per accessor.
+#[derive(Default)]
+struct Buffer {
+    data: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl Buffer {
+    fn add(
+        &mut self,
+        bytes: &[u8],
+        count: usize,
+        component_type: u32,
+        kind: &str,
+        target: Option<u32>,
+    ) -> usize {
+        self.data.resize(self.data.len().next_multiple_of(4), 0);
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.data.len(),
+            "byteLength": bytes.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = target.into();
+        }
+        self.data.extend_from_slice(bytes);
+        self.views.push(view);
+        self.accessors.push(json!({
+            "bufferView": self.views.len() - 1,
+            "componentType": component_type,
+            "count": count,
+            "type": kind,
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn add_floats<const N: usize>(&mut self, items: &[[f32; N]], kind: &str) -> usize {
+        let bytes = items
+            .iter()
+            .flatten()
+            .flat_map(|x| x.to_le_bytes())
+            .collect::<Vec<_>>();
+        self.add(&bytes, items.len(), FLOAT, kind, Some(ARRAY_BUFFER))
+    }
+
+    /// Positions, which need their bounds.
+    fn add_positions(&mut self, positions: &[[f32; 3]]) -> usize {
+        let accessor = self.add_floats(positions, "VEC3");
+        let (min, max) = positions.iter().fold(
+            (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+            |(min, max), position| (min.min((*position).into()), max.max((*position).into())),
+        );
+        self.accessors[accessor]["min"] = json!(min.to_array());
+        self.accessors[accessor]["max"] = json!(max.to_array());
+        accessor
+    }
+
+    fn add_joints(&mut self, joints: &[[u16; 4]]) -> usize {
+        let bytes = joints
+            .iter()
+            .flatten()
+            .flat_map(|x| x.to_le_bytes())
+            .collect::<Vec<_>>();
+        self.add(
+            &bytes,
+            joints.len(),
+            UNSIGNED_SHORT,
+            "VEC4",
+            Some(ARRAY_BUFFER),
+        )
+    }
+
+    fn add_indices(&mut self, indices: &[u32]) -> usize {
+        let bytes = indices
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect::<Vec<_>>();
+        let target = Some(ELEMENT_ARRAY_BUFFER);
+        self.add(&bytes, indices.len(), UNSIGNED_INT, "SCALAR", target)
+    }
+
+    fn add_matrices(&mut self, matrices: &[Mat4]) -> usize {
+        let bytes = matrices
+            .iter()
+            .flat_map(|matrix| matrix.to_cols_array())
+            .flat_map(|x| x.to_le_bytes())
+            .collect::<Vec<_>>();
+        self.add(&bytes, matrices.len(), FLOAT, "MAT4", None)
+    }
+}
+
+/// The joint of a stem, at the base of its segment.
+struct Joint {
+    name: String,
+    parent: Option<usize>,
+    base: Vec3,
+    tip: Vec3,
+    rotation: Quat,
+    organ: Option<Organ>,
+    is_root: bool,
+}
+impl Joint {
+    fn transform(&self) -> Transform {
+        Transform::from_translation(self.base).with_rotation(self.rotation)
+    }
+    /// Distance of `point` from the segment, and how far along it the nearest point lies.
+    fn distance(&self, point: Vec3) -> (f32, f32) {
+        let segment = self.tip - self.base;
+        let t = if segment.length_squared() > 0.0 {
+            ((point - self.base).dot(segment) / segment.length_squared()).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        (point.distance(self.base + segment * t), t)
+    }
+}
+
+/// Skins `point` to the joint of the nearest segment among `candidates`. Over the lower half of
+/// the segment it is blended with the joint below, so the stem bends smoothly at the joint.
+fn skin_weights(joints: &[Joint], candidates: &[usize], point: Vec3) -> ([u16; 4], [f32; 4]) {
+    let nearest = candidates
+        .iter()
+        .map(|joint| (*joint, joints[*joint].distance(point)))
+        .min_by(|(_, (a, _)), (_, (b, _))| a.total_cmp(b));
+    match nearest {
+        Some((joint, (_, t))) => match joints[joint].parent {
+            Some(parent) if t < 0.5 => (
+                [joint as u16, parent as u16, 0, 0],
+                [0.5 + t, 0.5 - t, 0.0, 0.0],
+            ),
+            _ => ([joint as u16, 0, 0, 0], [1.0, 0.0, 0.0, 0.0]),
+        },
+        None => ([0; 4], [1.0, 0.0, 0.0, 0.0]),
+    }
+}
+
+/// The faces of `mesh` grouped by organ, in the order the organs first appear.
+fn organ_faces(mesh: &MeshMap) -> Vec<(Option<Organ>, Vec<FaceId>)> {
+    let mut groups = Vec::<(Option<Organ>, Vec<FaceId>)>::new();
+    let mut indices = HashMap::new();
+    for face in mesh.face_iter() {
+        let label = mesh.face_label(face).and_then(FaceLabel::organ);
+        let index = *indices.entry(label).or_insert_with(|| {
+            groups.push((label, Vec::new()));
+            groups.len() - 1
+        });
+        groups[index].1.push(face);
+    }
+    groups
+}
+
+/// A metallic-roughness material, vertex colored, for the organs of `kind`.
+fn material(kind: Option<OrganKind>) -> Value {
+    let roughness = match kind {
+        Some(OrganKind::Fruit) => 0.35,
+        Some(OrganKind::Leaflet) => 0.55,
+        Some(OrganKind::Flower | OrganKind::Calyx) => 0.65,
+        _ => 0.8,
+    };
+    json!({
+        "name": kind.map_or("Plant".into(), |kind| format!("{kind:?}")),
+        "pbrMetallicRoughness": {
+            "baseColorFactor": [1.0, 1.0, 1.0, 1.0],
+            "metallicFactor": 0.0,
+            "roughnessFactor": roughness,
+        },
+        // Blades and petals are single sheets.
+        "doubleSided": true,
+    })
+}
+
+/// A skinned mesh of the `faces` of one organ, with its own copy of their vertices.
+fn organ_mesh(
+    buffer: &mut Buffer,
+    mesh: &MeshMap,
+    label: Option<Organ>,
+    faces: &[FaceId],
+    material: usize,
+    skin: impl Fn(Vec3) -> ([u16; 4], [f32; 4]),
+) -> Value {
+    let mut vertices = HashMap::<VertexId, u32>::new();
+    let mut order = Vec::new();
+    let indices = faces
+        .iter()
+        .flat_map(|face| mesh.face_vertices(*face))
+        .map(|vertex| {
+            *vertices.entry(vertex).or_insert_with(|| {
+                order.push(vertex);
+                order.len() as u32 - 1
+            })
+        })
+        .collect::<Vec<_>>();
+    let positions = order
+        .iter()
+        .map(|vertex| mesh.vertex_position(*vertex))
+        .collect::<Vec<_>>();
+    let normals = order
+        .iter()
+        .map(|vertex| mesh.vertex_normal(*vertex))
+        .collect::<Vec<_>>();
+    let uvs = order
+        .iter()
+        .map(|vertex| mesh.vertex_uv(*vertex))
+        .collect::<Vec<_>>();
+    let colors = order
+        .iter()
+        .map(|vertex| mesh.vertex_color(*vertex))
+        .collect::<Vec<_>>();
+    let (joints, weights): (Vec<_>, Vec<_>) = positions
+        .iter()
+        .map(|position| skin((*position).into()))
+        .unzip();
+    json!({
+        "name": group_name(label.map(FaceLabel::Organ)),
+        "primitives": [{
+            "attributes": {
+                "POSITION": buffer.add_positions(&positions),
+                "NORMAL": buffer.add_floats(&normals, "VEC3"),
+                "TEXCOORD_0": buffer.add_floats(&uvs, "VEC2"),
+                "COLOR_0": buffer.add_floats(&colors, "VEC4"),
+                "JOINTS_0": buffer.add_joints(&joints),
+                "WEIGHTS_0": buffer.add_floats(&weights, "VEC4"),
+            },
+            "indices": buffer.add_indices(&indices),
+            "material": material,
+        }],
+    })
+}
+
+/// Builds the glTF assets of plants, see [`PlantGltf`].
+#[allow(clippy::type_complexity)]
+#[derive(SystemParam)]
+pub struct PlantExporter<'w, 's> {
+    meshes: PlantMeshBuilder<'w, 's>,
+    roots: Query<'w, 's, (Entity, &'static PlantRoot)>,
+    stems: Query<
+        'w,
+        's,
+        (
+            (
+                Entity,
+                &'static Transform,
+                Option<&'static Name>,
+                Option<&'static Organ>,
+                Has<RootStem>,
+                Has<Dormant>,
+            ),
+            Relations<AxisUp>,
+        ),
+    >,
+    names: Query<'w, 's, &'static Name>,
+}
+
+impl PlantExporter<'_, '_> {
+    /// Exports `plant`, or `None` if it has not been generated. The daughter plants on its
+    /// runners are plants of their own, exported separately with their skeleton rooted at the
+    /// runner node. Fails if the plant has more stems than glTF can index joints.
+    pub fn export(&self, plant: Entity) -> Result<Option<PlantGltf>, MeshIoError> {
+        let meshes = (self.meshes.build().into_iter())
+            .filter(|((owner, _), _)| *owner == plant)
+            .map(|((_, is_root), mesh)| (is_root, mesh))
+            .collect();
+        self.export_meshes(plant, meshes)
+    }
+
+    /// Exports each of `plants` like [`export`](Self::export), building the meshes of all
+    /// plants only once.
+    pub fn export_all(
+        &self,
+        plants: impl IntoIterator<Item = Entity>,
+    ) -> Vec<(Entity, Result<Option<PlantGltf>, MeshIoError>)> {
+        let mut plant_meshes = HashMap::<Entity, Vec<(bool, MeshMap)>>::new();
+        for ((plant, is_root), mesh) in self.meshes.build() {
+            plant_meshes.entry(plant).or_default().push((is_root, mesh));
+        }
+        (plants.into_iter())
+            .map(|plant| {
+                let meshes = plant_meshes.remove(&plant).unwrap_or_default();
+                (plant, self.export_meshes(plant, meshes))
+            })
+            .collect()
+    }
+
+    /// Exports `plant` with its shoot and root meshes, each tagged with whether it holds roots.
+    fn export_meshes(
+        &self,
+        plant: Entity,
+        mut plant_meshes: Vec<(bool, MeshMap)>,
+    ) -> Result<Option<PlantGltf>, MeshIoError> {
+        let Some(joints) = self
+            .roots
+            .iter()
+            .find(|(_, root)| root.0 == plant)
+            .and_then(|(root, _)| self.joints(root, plant))
+        else {
+            return Ok(None);
+        };
+        if joints.len() > MAX_JOINTS {
+            return Err(MeshIoError::Unsupported(format!(
+                "{} joints, but glTF cannot index more than {MAX_JOINTS}",
+                joints.len()
+            )));
+        }
+
+        // Vertices are skinned to the stems of their own organ. Flowers, fruits and calyxes
+        // follow their pedicel.
+        let mut organ_joints = HashMap::<OrganId, Vec<usize>>::new();
+        for (index, joint) in joints.iter().enumerate() {
+            let Some(organ) = joint.organ else {
+                continue;
+            };
+            let fruit = organ.part(OrganKind::Fruit);
+            for part in [
+                organ,
+                organ.part(OrganKind::Flower),
+                organ.part(OrganKind::Calyx),
+                fruit,
+                fruit.part(OrganKind::Calyx),
+            ] {
+                organ_joints.entry(part.id).or_default().push(index);
+            }
+        }
+        let mut buffer = Buffer::default();
+        let mut children = vec![Vec::new(); joints.len()];
+        for (index, joint) in joints.iter().enumerate() {
+            if let Some(parent) = joint.parent {
+                children[parent].push(index);
+            }
+        }
+        let mut nodes = joints
+            .iter()
+            .zip(children)
+            .map(|(joint, children)| {
+                let parent = joint.parent.map_or(Mat4::IDENTITY, |parent| {
+                    joints[parent].transform().compute_matrix()
+                });
+                let local =
+                    Transform::from_matrix(parent.inverse() * joint.transform().compute_matrix());
+                let mut node = json!({
+                    "name": joint.name,
+                    "translation": local.translation.to_array(),
+                    "rotation": local.rotation.to_array(),
+                });
+                if !children.is_empty() {
+                    node["children"] = json!(children);
+                }
+                node
+            })
+            .collect::<Vec<_>>();
+        let inverse_binds = joints
+            .iter()
+            .map(|joint| joint.transform().compute_matrix().inverse())
+            .collect::<Vec<_>>();
+        let skin = json!({
+            "inverseBindMatrices": buffer.add_matrices(&inverse_binds),
+            "joints": (0..joints.len()).collect::<Vec<_>>(),
+            "skeleton": 0,
+        });
+
+        plant_meshes.sort_by_key(|(is_root, _)| *is_root);
+        let mut meshes = Vec::new();
+        let mut materials = Vec::new();
+        let mut kind_materials = HashMap::new();
+        for (is_root, mesh) in &plant_meshes {
+            // Faces without stems of their own follow the nearest stem of their mesh.
+            let fallback = (0..joints.len())
+                .filter(|joint| joints[*joint].is_root == *is_root)
+                .collect::<Vec<_>>();
+            for (label, faces) in organ_faces(mesh) {
+                let candidates = label
+                    .and_then(|organ| organ_joints.get(&organ.id))
+                    .unwrap_or(&fallback);
+                let kind = label.map(|organ| organ.kind);
+                let material = *kind_materials.entry(kind).or_insert_with(|| {
+                    materials.push(material(kind));
+                    materials.len() - 1
+                });
+                let mut organ_mesh =
+                    organ_mesh(&mut buffer, mesh, label, &faces, material, |point| {
+                        skin_weights(&joints, candidates, point)
+                    });
+                let mut node = json!({
+                    "name": group_name(label.map(FaceLabel::Organ)),
+                    "mesh": meshes.len(),
+                    "skin": 0,
+                });
+                // Defective fruits carry the kinds of their defects as ground truth.
+                let defects = label.map(|organ| organ.defects).unwrap_or_default();
+                if !defects.is_empty() {
+                    let extras = json!({
+                        "defects": defects.iter().map(|kind| format!("{kind:?}")).collect::<Vec<_>>(),
+                    });
+                    organ_mesh["extras"] = extras.clone();
+                    node["extras"] = extras;
+                }
+                meshes.push(organ_mesh);
+                nodes.push(node);
+            }
+        }
+
+        let name = self
+            .names
+            .get(plant)
+            .map_or_else(|_| "Strawberry plant".into(), ToString::to_string);
+        // Skinned meshes are placed by their joints, so they sit at the root of the scene next
+        // to the skeleton.
+        let roots = std::iter::once(0)
+            .chain(joints.len()..nodes.len())
+            .collect::<Vec<_>>();
+        let document = json!({
+            "asset": { "version": "2.0", "generator": "strawberry_gen" },
+            "scene": 0,
+            "scenes": [{ "name": name, "nodes": roots }],
+            "nodes": nodes,
+            "meshes": meshes,
+            "materials": materials,
+            "skins": [skin],
+            "accessors": buffer.accessors,
+            "bufferViews": buffer.views,
+            "buffers": [{ "byteLength": buffer.data.len() }],
+        });
+        Ok(Some(PlantGltf {
+            document,
+            buffer: buffer.data,
+        }))
+    }
+
+    /// The joints of the stems of `plant` from `root` up, each after its parent. The root
+    /// particle has no segment, so its joint sits on it.
+    fn joints(&self, root: Entity, plant: Entity) -> Option<Vec<Joint>> {
+        let ((_, transform, name, organ, is_root, _), _) = self.stems.get(root).ok()?;
+        // The runner node a daughter roots at is an organ of its mother.
+        let organ = organ.filter(|organ| organ.plant == plant);
+        let mut joints = vec![Joint {
+            name: name.map_or_else(|| "Plant".into(), ToString::to_string),
+            parent: None,
+            base: transform.translation,
+            tip: transform.translation,
+            rotation: transform.rotation,
+            organ: organ.copied(),
+            is_root,
+        }];
+        let mut indices = HashMap::from([(root, 0)]);
+        self.stems.traverse::<AxisUp>([root]).track_self().for_each(
+            |(parent, parent_transform, ..),
+             _,
+             (this, transform, name, organ, is_root, dormant),
+             _| {
+                // Stems that have not emerged have no mesh to move. What they bear has not
+                // emerged either.
+                if *dormant || organ.map(|organ| organ.plant) != Some(plant) {
+                    return;
+                }
+                indices.insert(*this, joints.len());
+                joints.push(Joint {
+                    name: name
+                        .map_or_else(|| format!("Stem {}", joints.len()), ToString::to_string),
+                    parent: Some(indices[parent]),
+                    base: parent_transform.translation,
+                    tip: transform.translation,
+                    rotation: transform.rotation,
+                    organ: organ.copied(),
+                    is_root: *is_root,
+                });
+            },
+        );
+        Some(joints)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_utils::{spawn_plant, test_app},
+        DefectKind, Fruit, FruitDefect, PlantSpec, RunnerAnchor,
+    };
+
+    fn floats(gltf: &PlantGltf, accessor: &Value) -> Vec<f32> {
+        let view = &gltf.document["bufferViews"][accessor["bufferView"].as_u64().unwrap() as usize];
+        let offset = view["byteOffset"].as_u64().unwrap() as usize;
+        let length = view["byteLength"].as_u64().unwrap() as usize;
+        gltf.buffer[offset..offset + length]
+            .chunks(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_skeleton_binds_the_rest_pose() {
+        let mut app = test_app();
+        let plant = spawn_plant(&mut app, PlantSpec::default());
+        let mut fruits = app.world.query::<&mut Fruit>();
+        let mut fruit = fruits.iter_mut(&mut app.world).next().unwrap();
+        fruit.shape.defects.push(FruitDefect {
+            kind: DefectKind::BirdPeck,
+            t: 0.6,
+            angle: 0.0,
+            radius: 0.1,
+            severity: 1.0,
+            seed: 0,
+        });
+        let gltf = PlantGltf::from_world(&mut app.world, plant)
+            .unwrap()
+            .unwrap();
+        let document = &gltf.document;
+
+        // Walking the joint nodes down from the skeleton root gives back the rest pose the
+        // inverse bind matrices undo.
+        let accessors = document["accessors"].as_array().unwrap();
+        let skin = &document["skins"][0];
+        let inverse_binds = floats(
+            &gltf,
+            &accessors[skin["inverseBindMatrices"].as_u64().unwrap() as usize],
+        );
+        let joint_count = skin["joints"].as_array().unwrap().len();
+        assert!(joint_count > 10);
+        let mut stack = vec![(0, Mat4::IDENTITY)];
+        let mut visited = 0;
+        while let Some((index, parent)) = stack.pop() {
+            let node = &document["nodes"][index];
+            let vector = |key: &str| {
+                node[key]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|x| x.as_f64().unwrap() as f32)
+                    .collect::<Vec<_>>()
+            };
+            let local = Transform::from_translation(Vec3::from_slice(&vector("translation")))
+                .with_rotation(Quat::from_slice(&vector("rotation")));
+            let global = parent * local.compute_matrix();
+            let inverse_bind = Mat4::from_cols_slice(&inverse_binds[index * 16..index * 16 + 16]);
+            assert!((global * inverse_bind).abs_diff_eq(Mat4::IDENTITY, 1e-3));
+            visited += 1;
+            for child in node["children"].as_array().into_iter().flatten() {
+                stack.push((child.as_u64().unwrap() as usize, global));
+            }
+        }
+        assert_eq!(visited, joint_count);
+
+        // Every organ has a mesh, skinned with weights adding up to one.
+        let meshes = document["meshes"].as_array().unwrap();
+        assert!(meshes
+            .iter()
+            .any(|mesh| mesh["name"].as_str().unwrap().starts_with("Fruit_")));
+        for mesh in meshes {
+            let attributes = &mesh["primitives"][0]["attributes"];
+            let accessor = |name: &str| &accessors[attributes[name].as_u64().unwrap() as usize];
+            let count = accessor("POSITION")["count"].as_u64().unwrap();
+            for name in ["NORMAL", "TEXCOORD_0", "COLOR_0", "JOINTS_0", "WEIGHTS_0"] {
+                assert_eq!(accessor(name)["count"].as_u64().unwrap(), count);
+            }
+            for weights in floats(&gltf, accessor("WEIGHTS_0")).chunks(4) {
+                assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+            }
+        }
+
+        let mut glb = Vec::new();
+        gltf.write_glb(&mut glb).unwrap();
+        assert_eq!(&glb[..4], b"glTF");
+        assert_eq!(
+            u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize,
+            glb.len()
+        );
+        let mut json = Vec::new();
+        gltf.write_gltf(&mut json).unwrap();
+        let written: Value = serde_json::from_slice(&json).unwrap();
+        let uri = written["buffers"][0]["uri"].as_str().unwrap();
+        let data = uri
+            .strip_prefix("data:application/octet-stream;base64,")
+            .unwrap();
+        assert_eq!(data.len(), gltf.buffer.len().div_ceil(3) * 4);
+        assert_eq!(base64(b"glTF!"), "Z2xURiE=");
+    }
+
+    #[test]
+    fn test_daughters_are_exported_on_their_own() {
+        let mut app = test_app();
+        let mother = spawn_plant(&mut app, PlantSpec::default());
+        let mut daughters = app.world.query_filtered::<Entity, With<RunnerAnchor>>();
+        let daughters = daughters.iter(&app.world).collect::<Vec<_>>();
+        assert!(!daughters.is_empty());
+
+        let kinds = |app: &mut App, plant| {
+            let gltf = PlantGltf::from_world(&mut app.world, plant)
+                .unwrap()
+                .unwrap();
+            gltf.document["meshes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|mesh| {
+                    let name = mesh["name"].as_str().unwrap();
+                    name.split('_').next().unwrap().parse::<OrganKind>().ok()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert!(kinds(&mut app, mother).contains(&Some(OrganKind::Runner)));
+        for &daughter in &daughters {
+            let kinds = kinds(&mut app, daughter);
+            assert!(kinds.contains(&Some(OrganKind::Leaflet)));
+            assert!(!kinds.contains(&Some(OrganKind::Runner)));
+        }
+
+        // Exporting all plants at once gives each the same asset as exporting it alone.
+        let plants = [vec![mother], daughters].concat();
+        let documents = app.world.run_system_once_with(
+            plants.clone(),
+            |In(plants): In<Vec<Entity>>, exporter: PlantExporter| {
+                (exporter.export_all(plants).into_iter())
+                    .map(|(_, gltf)| gltf.unwrap().unwrap().document)
+                    .collect::<Vec<_>>()
+            },
+        );
+        for (plant, document) in plants.into_iter().zip(documents) {
+            let gltf = PlantGltf::from_world(&mut app.world, plant)
+                .unwrap()
+                .unwrap();
+            assert_eq!(gltf.document, document);
+        }
+    }
+}
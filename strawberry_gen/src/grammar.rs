@@ -0,0 +1,672 @@
+use std::{fmt, path::Path};
+
+use bevy::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    spawn_blossom, spawn_leaf, spawn_runners, spawn_stem_chain, spawn_truss, OrganKind, OrganSpec,
+    PlantSpec, SizeDistribution, StemChain,
+};
+
+/// A parametric L-system describing the architecture of a plant, used instead of the
+/// crown/rosette layout of [`PlantSpec`] when present on a
+/// [`StrawberryPlant`](crate::StrawberryPlant).
+///
+/// The text format has one statement per line, `#` starts a comment:
+///
+/// ```text
+/// iterations: 5
+/// angle: 137.5
+/// axiom: F(0.2, 0.15) A(5)
+/// A(n) : n > 0 -> /[&(35)L]A(n - 1)
+/// ```
+///
+/// A rule rewrites a symbol with the given number of parameters into its successor, if its
+/// optional condition holds. The first matching rule wins, symbols without one are kept.
+///
+/// After the last iteration the symbols are interpreted as a turtle walking up the plant:
+///
+/// | Symbol | Meaning |
+/// |--------|---------|
+/// | `F(length, size)` | an [`Internode`](crate::OrganKind::Internode) stem hanging off the current one along `AxisUp` |
+/// | `+(a)` `-(a)` | turn around the local Z axis by `a` degrees |
+/// | `&(a)` `^(a)` | pitch around the local X axis |
+/// | `/(a)` `\(a)` | roll around the stem axis |
+/// | `[` `]` | start and end a branch |
+/// | `L` | a petiole with its leaflets, from [`PlantSpec::petiole`] and [`PlantSpec::leaflet`] |
+/// | `T` | a truss, from [`PlantSpec::truss`] and [`PlantSpec::inflorescence`] |
+/// | `K(maturity)` | a flower on the current stem, with a fruit from a maturity of zero |
+/// | `R` | the runners of [`PlantSpec::runner`] |
+///
+/// Turns without a parameter use `angle`, missing sizes and maturities are drawn from the
+/// [`PlantSpec`]. Any other symbol only takes part in the rewriting.
+#[derive(Component, Debug, Clone)]
+pub struct PlantGrammar {
+    pub axiom: Vec<Module>,
+    pub rules: Vec<Rule>,
+    pub iterations: u32,
+    /// Default turning angle in degrees.
+    pub angle: f32,
+}
+
+/// A symbol with its actual parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub symbol: char,
+    pub params: Vec<f32>,
+}
+
+/// Rewrites a module with `symbol` and as many parameters as `params` names.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub symbol: char,
+    pub params: Vec<String>,
+    pub condition: Option<Condition>,
+    pub successor: Vec<(char, Vec<Expr>)>,
+}
+
+/// Arithmetic on the formal parameters of a rule, which are referred to by index.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f32),
+    Param(usize),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, BinaryOp, Box<Expr>),
+}
+impl Expr {
+    pub fn eval(&self, params: &[f32]) -> f32 {
+        match self {
+            Self::Number(value) => *value,
+            Self::Param(index) => params[*index],
+            Self::Neg(expr) => -expr.eval(params),
+            Self::Binary(a, op, b) => op.apply(a.eval(params), b.eval(params)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+impl BinaryOp {
+    fn from_symbol(c: char) -> Option<Self> {
+        match c {
+            '+' => Some(Self::Add),
+            '-' => Some(Self::Sub),
+            '*' => Some(Self::Mul),
+            '/' => Some(Self::Div),
+            _ => None,
+        }
+    }
+    pub fn apply(self, a: f32, b: f32) -> f32 {
+        match self {
+            Self::Add => a + b,
+            Self::Sub => a - b,
+            Self::Mul => a * b,
+            Self::Div => a / b,
+        }
+    }
+}
+
+/// Comparison of two expressions guarding a rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub left: Expr,
+    pub op: CompareOp,
+    pub right: Expr,
+}
+impl Condition {
+    pub fn holds(&self, params: &[f32]) -> bool {
+        self.op
+            .compare(self.left.eval(params), self.right.eval(params))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+impl CompareOp {
+    /// The comparison written as `op`, like `<=`.
+    fn from_symbol(op: &str) -> Option<Self> {
+        match op {
+            "<" => Some(Self::Less),
+            "<=" => Some(Self::LessEqual),
+            ">" => Some(Self::Greater),
+            ">=" => Some(Self::GreaterEqual),
+            "==" => Some(Self::Equal),
+            "!=" => Some(Self::NotEqual),
+            _ => None,
+        }
+    }
+    pub fn compare(self, a: f32, b: f32) -> bool {
+        match self {
+            Self::Less => a < b,
+            Self::LessEqual => a <= b,
+            Self::Greater => a > b,
+            Self::GreaterEqual => a >= b,
+            Self::Equal => a == b,
+            Self::NotEqual => a != b,
+        }
+    }
+}
+
+/// Most modules a derivation may produce, which keeps a grammar that grows without bound from
+/// exhausting memory.
+pub const MAX_MODULES: usize = 1 << 20;
+
+#[derive(Debug)]
+pub enum GrammarError {
+    Io(std::io::Error),
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// The derivation outgrew [`MAX_MODULES`] at `iteration`.
+    TooManyModules {
+        iteration: u32,
+        modules: usize,
+    },
+}
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "cannot read grammar: {error}"),
+            Self::Parse { line, message } => write!(f, "line {line}: {message}"),
+            Self::TooManyModules { iteration, modules } => write!(
+                f,
+                "iteration {iteration} derives {modules} modules, more than {MAX_MODULES}"
+            ),
+        }
+    }
+}
+impl std::error::Error for GrammarError {}
+impl From<std::io::Error> for GrammarError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl PlantGrammar {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, GrammarError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, GrammarError> {
+        let mut grammar = Self {
+            axiom: Vec::new(),
+            rules: Vec::new(),
+            iterations: 1,
+            angle: 90.0,
+        };
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let error = |message: String| GrammarError::Parse {
+                line: line_number,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some((key, value)) = line
+                .split_once(':')
+                .filter(|(key, _)| matches!(key.trim(), "iterations" | "angle" | "axiom"))
+            {
+                let value = value.trim();
+                match key.trim() {
+                    "iterations" => {
+                        grammar.iterations = value
+                            .parse()
+                            .map_err(|_| error(format!("invalid iteration count `{value}`")))?
+                    }
+                    "angle" => {
+                        grammar.angle = value
+                            .parse()
+                            .map_err(|_| error(format!("invalid angle `{value}`")))?
+                    }
+                    _ => {
+                        let modules = Parser::new(value, &[]).modules().map_err(error)?;
+                        grammar.axiom = modules
+                            .into_iter()
+                            .map(|(symbol, params)| Module {
+                                symbol,
+                                params: params.iter().map(|param| param.eval(&[])).collect(),
+                            })
+                            .collect();
+                    }
+                }
+                continue;
+            }
+            grammar.rules.push(Self::parse_rule(line).map_err(error)?);
+        }
+        Ok(grammar)
+    }
+
+    fn parse_rule(line: &str) -> Result<Rule, String> {
+        let (head, successor) = line
+            .split_once("->")
+            .ok_or_else(|| format!("expected `key: value` or a rule, found `{line}`"))?;
+        let (predecessor, condition) = match head.split_once(':') {
+            Some((predecessor, condition)) => (predecessor, Some(condition)),
+            None => (head, None),
+        };
+
+        let predecessor = predecessor.trim();
+        let mut chars = predecessor.chars();
+        let symbol = chars
+            .next()
+            .ok_or_else(|| "rule without a predecessor".to_string())?;
+        let params = chars.as_str().trim();
+        let params = if params.is_empty() {
+            Vec::new()
+        } else {
+            params
+                .strip_prefix('(')
+                .and_then(|params| params.strip_suffix(')'))
+                .ok_or_else(|| format!("invalid predecessor `{predecessor}`"))?
+                .split(',')
+                .map(|param| param.trim().to_string())
+                .collect()
+        };
+
+        let condition = condition
+            .map(|condition| Parser::new(condition, &params).condition())
+            .transpose()?;
+        let successor = Parser::new(successor, &params).modules()?;
+        Ok(Rule {
+            symbol,
+            params,
+            condition,
+            successor,
+        })
+    }
+
+    /// Rewrites the axiom `iterations` times. Fails once the string of modules outgrows
+    /// [`MAX_MODULES`].
+    pub fn derive(&self) -> Result<Vec<Module>, GrammarError> {
+        let mut modules = self.axiom.clone();
+        for iteration in 1..=self.iterations {
+            modules = modules
+                .into_iter()
+                .flat_map(|module| {
+                    let rule = self.rules.iter().find(|rule| {
+                        rule.symbol == module.symbol
+                            && rule.params.len() == module.params.len()
+                            && rule
+                                .condition
+                                .iter()
+                                .all(|condition| condition.holds(&module.params))
+                    });
+                    match rule {
+                        Some(rule) => rule
+                            .successor
+                            .iter()
+                            .map(|(symbol, params)| Module {
+                                symbol: *symbol,
+                                params: params
+                                    .iter()
+                                    .map(|param| param.eval(&module.params))
+                                    .collect(),
+                            })
+                            .collect(),
+                        None => vec![module],
+                    }
+                })
+                .collect();
+            if modules.len() > MAX_MODULES {
+                return Err(GrammarError::TooManyModules {
+                    iteration,
+                    modules: modules.len(),
+                });
+            }
+        }
+        Ok(modules)
+    }
+}
+
+/// Recursive descent over a single line of modules, expressions or a condition.
+struct Parser<'a> {
+    chars: Vec<char>,
+    position: usize,
+    params: &'a [String],
+}
+impl<'a> Parser<'a> {
+    fn new(text: &str, params: &'a [String]) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            position: 0,
+            params,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        while self
+            .chars
+            .get(self.position)
+            .is_some_and(|c| c.is_whitespace())
+        {
+            self.position += 1;
+        }
+        self.chars.get(self.position).copied()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(c) => Err(format!("expected `{expected}`, found `{c}`")),
+            None => Err(format!("expected `{expected}` at the end of the line")),
+        }
+    }
+
+    fn modules(&mut self) -> Result<Vec<(char, Vec<Expr>)>, String> {
+        let mut modules = Vec::new();
+        while let Some(symbol) = self.peek() {
+            self.position += 1;
+            let mut params = Vec::new();
+            if self.peek() == Some('(') {
+                self.position += 1;
+                loop {
+                    params.push(self.expr()?);
+                    if self.peek() == Some(',') {
+                        self.position += 1;
+                    } else {
+                        self.expect(')')?;
+                        break;
+                    }
+                }
+            }
+            modules.push((symbol, params));
+        }
+        Ok(modules)
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let left = self.expr()?;
+        let op = match self.peek() {
+            Some(c @ ('<' | '>' | '=' | '!')) => {
+                self.position += 1;
+                let mut op = c.to_string();
+                if self.chars.get(self.position) == Some(&'=') {
+                    self.position += 1;
+                    op.push('=');
+                }
+                CompareOp::from_symbol(&op).ok_or_else(|| format!("invalid comparison `{op}`"))?
+            }
+            _ => return Err("expected a comparison".to_string()),
+        };
+        let right = self.expr()?;
+        if let Some(c) = self.peek() {
+            return Err(format!("unexpected `{c}` after the condition"));
+        }
+        Ok(Condition { left, op, right })
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+        while let Some(op @ (BinaryOp::Add | BinaryOp::Sub)) =
+            self.peek().and_then(BinaryOp::from_symbol)
+        {
+            self.position += 1;
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.term()?));
+        }
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut expr = self.factor()?;
+        while let Some(op @ (BinaryOp::Mul | BinaryOp::Div)) =
+            self.peek().and_then(BinaryOp::from_symbol)
+        {
+            self.position += 1;
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.factor()?));
+        }
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some('-') => {
+                self.position += 1;
+                Ok(Expr::Neg(Box::new(self.factor()?)))
+            }
+            Some('(') => {
+                self.position += 1;
+                let expr = self.expr()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.position;
+                while self
+                    .chars
+                    .get(self.position)
+                    .is_some_and(|c| c.is_ascii_digit() || *c == '.')
+                {
+                    self.position += 1;
+                }
+                let number: String = self.chars[start..self.position].iter().collect();
+                number
+                    .parse()
+                    .map(Expr::Number)
+                    .map_err(|_| format!("invalid number `{number}`"))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let start = self.position;
+                while self
+                    .chars
+                    .get(self.position)
+                    .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+                {
+                    self.position += 1;
+                }
+                let name: String = self.chars[start..self.position].iter().collect();
+                self.params
+                    .iter()
+                    .position(|param| *param == name)
+                    .map(Expr::Param)
+                    .ok_or_else(|| format!("unknown parameter `{name}`"))
+            }
+            Some(c) => Err(format!("unexpected `{c}` in an expression")),
+            None => Err("expected an expression at the end of the line".to_string()),
+        }
+    }
+}
+
+/// Spawns the organs of a derived grammar off `base`. `rotation` turns the first stem
+/// relative to `base`. A grammar that cannot be derived spawns nothing.
+pub(crate) fn spawn_grammar(
+    commands: &mut Commands,
+    rng: &mut ChaCha8Rng,
+    base: &StemChain,
+    spec: &PlantSpec,
+    grammar: &PlantGrammar,
+    rotation: Quat,
+) {
+    let modules = match grammar.derive() {
+        Ok(modules) => modules,
+        Err(error) => {
+            error!(
+                "Cannot derive the grammar of plant {:?}: {error}",
+                base.plant
+            );
+            return;
+        }
+    };
+    let mut chain = base.clone();
+    let mut turn = rotation;
+    let mut branches = Vec::new();
+    let mut counts = [0; 4];
+    let mut name = |kind: usize, label: &str| {
+        counts[kind] += 1;
+        format!("{label} {}", counts[kind] - 1)
+    };
+    for module in modules {
+        let param = |index: usize| module.params.get(index).copied();
+        let angle = param(0).unwrap_or(grammar.angle).to_radians();
+        match module.symbol {
+            'F' => {
+                let length = param(0).unwrap_or(1.0);
+                let size = param(1).unwrap_or(spec.crown.size.mean);
+                let organ = OrganSpec::new(
+                    SizeDistribution::constant(1.0),
+                    1,
+                    SizeDistribution::constant(length),
+                    SizeDistribution::constant(size),
+                );
+                let name = name(0, "Internode");
+                chain = spawn_stem_chain(
+                    commands,
+                    rng,
+                    &name,
+                    OrganKind::Internode,
+                    &chain,
+                    &organ,
+                    turn,
+                );
+                turn = Quat::IDENTITY;
+            }
+            '+' => turn *= Quat::from_rotation_z(angle),
+            '-' => turn *= Quat::from_rotation_z(-angle),
+            '&' => turn *= Quat::from_rotation_x(angle),
+            '^' => turn *= Quat::from_rotation_x(-angle),
+            '/' => turn *= Quat::from_rotation_y(angle),
+            '\\' => turn *= Quat::from_rotation_y(-angle),
+            '[' => branches.push((chain.clone(), turn)),
+            ']' => {
+                if let Some(branch) = branches.pop() {
+                    (chain, turn) = branch;
+                }
+            }
+            'L' => {
+                spawn_leaf(commands, rng, &name(1, "Petiole"), &chain, spec, turn);
+            }
+            'T' => {
+                spawn_truss(commands, rng, &name(2, "Truss"), &chain, spec, turn);
+            }
+            'K' => {
+                let maturity = match param(0) {
+                    Some(maturity) => maturity,
+                    None => spec.fruit.maturity.sample(rng),
+                };
+                spawn_blossom(commands, rng, chain.tip, spec, maturity);
+            }
+            'R' => spawn_runners(commands, rng, &name(3, "Stolon"), &chain, spec),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_utils::{spawn_plant, test_app},
+        Organ, Stem,
+    };
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn test_parametric_rules() {
+        let grammar = PlantGrammar::parse(
+            "
+            # Three leaves on a spiral, then a truss.
+            iterations: 4
+            axiom: F(0.2) A(3)
+            A(n) : n > 0 -> /(137.5) [ &(40) L ] A(n - 1)
+            A(n) : n <= 0 -> T
+            ",
+        )
+        .unwrap();
+        let symbols: String = grammar
+            .derive()
+            .unwrap()
+            .iter()
+            .map(|module| module.symbol)
+            .collect();
+        assert_eq!(symbols, "F/[&L]/[&L]/[&L]T");
+        assert_eq!(grammar.derive().unwrap()[0].params, vec![0.2]);
+    }
+
+    #[test]
+    fn test_parse_errors_report_the_line() {
+        let error = PlantGrammar::parse("axiom: A(1)\nA(n) : m > 0 -> A(n)").unwrap_err();
+        assert!(matches!(error, GrammarError::Parse { line: 2, .. }));
+    }
+
+    #[test]
+    fn test_bundled_grammar() {
+        let grammar = PlantGrammar::parse(include_str!("../grammars/strawberry.txt")).unwrap();
+        let modules = grammar.derive().unwrap();
+        assert!(modules.iter().any(|module| module.symbol == 'L'));
+        assert!(modules.iter().any(|module| module.symbol == 'T'));
+    }
+
+    #[test]
+    fn test_derivation_is_bounded() {
+        let grammar = PlantGrammar::parse("iterations: 30\naxiom: A\nA -> AA").unwrap();
+        assert!(matches!(
+            grammar.derive(),
+            Err(GrammarError::TooManyModules { iteration: 21, .. })
+        ));
+    }
+
+    #[test]
+    fn test_hand_built_rules() {
+        let rule = Rule {
+            symbol: 'A',
+            params: vec!["n".into()],
+            condition: Some(Condition {
+                left: Expr::Param(0),
+                op: CompareOp::Greater,
+                right: Expr::Number(0.0),
+            }),
+            successor: vec![(
+                'A',
+                vec![Expr::Binary(
+                    Box::new(Expr::Param(0)),
+                    BinaryOp::Sub,
+                    Box::new(Expr::Number(1.0)),
+                )],
+            )],
+        };
+        let grammar = PlantGrammar {
+            axiom: vec![Module {
+                symbol: 'A',
+                params: vec![2.0],
+            }],
+            rules: vec![rule],
+            iterations: 3,
+            angle: 90.0,
+        };
+        assert_eq!(grammar.derive().unwrap()[0].params, vec![0.0]);
+    }
+
+    #[test]
+    fn test_internodes_are_their_own_organs() {
+        let grammar = PlantGrammar::parse("axiom: F(0.2) F(0.2) L").unwrap();
+        let mut app = test_app();
+        spawn_plant(&mut app, (PlantSpec::default(), grammar));
+        let kinds = app
+            .world
+            .run_system_once(|stems: Query<(&Name, &Organ), With<Stem>>| {
+                stems
+                    .iter()
+                    .filter(|(name, _)| name.starts_with("Internode"))
+                    .map(|(_, organ)| organ.kind)
+                    .collect::<Vec<_>>()
+            });
+        assert_eq!(kinds, vec![OrganKind::Internode; 2]);
+    }
+}
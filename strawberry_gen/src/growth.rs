@@ -0,0 +1,393 @@
+use aery::prelude::*;
+use bevy::prelude::*;
+
+use crate::{
+    AxisUp, EdgeConstraint, Flower, FlowerState, Fruit, Organ, PlantSpec, Ripeness, RunnerAnchor,
+    Stem, P1,
+};
+
+/// Developmental stage of a plant, following from its [`PlantAge`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+pub enum GrowthStage {
+    Vegetative,
+    Flowering,
+    FruitSet,
+    Ripening,
+}
+
+/// Timing of the development of a plant, in days.
+///
+/// Every organ emerges some days after the organ it grows from, then elongates and thickens to
+/// its generated size over `elongation_days`.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct GrowthSpec {
+    /// Days an organ takes from emergence to its full size.
+    pub elongation_days: f32,
+    /// Days between two successive leaves on a crown.
+    pub plastochron: f32,
+    /// Day the first truss emerges.
+    pub flowering_day: f32,
+    /// Days between two successive trusses on a crown.
+    pub truss_interval: f32,
+    /// Days a branch of the next order emerges after its parent node.
+    pub order_days: f32,
+    /// Days from anthesis to fruit set.
+    pub fruit_set_days: f32,
+    /// Days from fruit set until the fruit is overripe.
+    pub ripening_days: f32,
+    /// Day the first internode of each runner emerges.
+    pub runner_day: f32,
+}
+impl Default for GrowthSpec {
+    fn default() -> Self {
+        Self {
+            elongation_days: 12.0,
+            plastochron: 7.0,
+            flowering_day: 35.0,
+            truss_interval: 10.0,
+            order_days: 4.0,
+            fruit_set_days: 5.0,
+            ripening_days: 35.0,
+            runner_day: 45.0,
+        }
+    }
+}
+impl GrowthSpec {
+    /// Stage of a plant `days` old.
+    pub fn stage(&self, days: f32) -> GrowthStage {
+        let anthesis = self.flowering_day + self.elongation_days;
+        match days {
+            d if d < self.flowering_day => GrowthStage::Vegetative,
+            d if d < anthesis + self.fruit_set_days => GrowthStage::Flowering,
+            d if d < anthesis + self.fruit_set_days + 0.25 * self.ripening_days => {
+                GrowthStage::FruitSet
+            }
+            _ => GrowthStage::Ripening,
+        }
+    }
+    /// Fraction of its full size an organ has reached `days` after its emergence.
+    pub fn growth(&self, days: f32) -> f32 {
+        let t = (days / self.elongation_days).clamp(0.0, 1.0);
+        (t * t * (3.0 - 2.0 * t)).max(MIN_GROWTH)
+    }
+}
+
+/// Size of an organ that has not emerged yet, relative to its full size. Keeps stems and
+/// constraints from degenerating to zero length.
+const MIN_GROWTH: f32 = 0.01;
+
+/// Age of a [`StrawberryPlant`](crate::StrawberryPlant) in days. Its organs are generated at
+/// full size and scaled down to this age, so the same plant can be rendered at any age.
+///
+/// `rate` advances the age by that many days per second of app time. A daughter plant on a
+/// runner of a plant with an age gets one of its own, counted from the day the runner node it
+/// roots at emerged and kept in step with its mother's.
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct PlantAge {
+    pub days: f32,
+    pub rate: f32,
+}
+impl PlantAge {
+    pub fn new(days: f32) -> Self {
+        Self { days, rate: 0.0 }
+    }
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.rate = rate;
+        self
+    }
+}
+
+/// Full-grown size of a stem, and the day it emerged on.
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct Growth {
+    pub size: f32,
+    pub length: f32,
+    pub emergence: f32,
+}
+impl Growth {
+    pub fn new(size: f32, length: f32) -> Self {
+        Self {
+            size,
+            length,
+            emergence: 0.0,
+        }
+    }
+}
+
+/// Marks a stem that has not emerged yet at the age of its plant. It is kept at a sliver of its
+/// size so its constraints do not degenerate, and is left out of the meshes and exports along
+/// with the leaflet, flower or fruit it bears.
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct Dormant;
+
+/// Days after its `AxisUp` parent a stem emerges. Set on the first stem of an organ; the rest
+/// of the organ emerges with it.
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct GrowthDelay(pub f32);
+
+/// Links the particle a plant grows from to the plant entity: its soil particle, or the runner
+/// node a daughter plant roots at.
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct PlantRoot(pub Entity);
+
+fn advance_plant_age(time: Res<Time>, mut ages: Query<&mut PlantAge>) {
+    for mut age in &mut ages {
+        if age.rate != 0.0 {
+            age.days += age.rate * time.delta_seconds();
+        }
+    }
+}
+
+/// Sets the age of every daughter plant from its mother's and the emergence of its node.
+fn age_daughters(
+    mut commands: Commands,
+    mothers: Query<&PlantAge, Without<RunnerAnchor>>,
+    mut daughters: Query<(Entity, &RunnerAnchor, Option<&mut PlantAge>)>,
+    nodes: Query<(&Organ, &Growth)>,
+) {
+    for (daughter, anchor, age) in &mut daughters {
+        let Ok((node, growth)) = nodes.get(anchor.particle) else {
+            continue;
+        };
+        let Ok(mother) = mothers.get(node.plant) else {
+            continue;
+        };
+        let days = mother.days - growth.emergence;
+        match age {
+            Some(mut age) => {
+                if age.days != days || age.rate != mother.rate {
+                    *age = PlantAge::new(days).with_rate(mother.rate);
+                }
+            }
+            None => {
+                commands
+                    .entity(daughter)
+                    .insert(PlantAge::new(days).with_rate(mother.rate));
+            }
+        }
+    }
+}
+
+/// Scales the stems of every plant with an age, from its [`PlantRoot`] up. The organs of other
+/// plants hanging off it, like daughters on runners, are left to their own plant.
+#[allow(clippy::type_complexity)]
+fn grow_plants(
+    mut commands: Commands,
+    roots: Query<(Entity, &PlantRoot)>,
+    plants: Query<(Ref<PlantAge>, Option<&PlantSpec>)>,
+    mut stems: Query<(
+        (
+            Entity,
+            &mut Stem,
+            Option<&mut Growth>,
+            Option<&GrowthDelay>,
+            Option<&Organ>,
+            Has<Dormant>,
+        ),
+        Relations<AxisUp>,
+    )>,
+    mut flowers: Query<(&mut Flower, Option<&Fruit>)>,
+) {
+    let default_spec = PlantSpec::default();
+    let new_stems = stems
+        .iter_mut()
+        .any(|((_, _, growth, ..), _)| growth.is_some_and(|growth| growth.is_added()));
+    for (root, plant) in &roots {
+        let Ok((age, spec)) = plants.get(plant.0) else {
+            continue;
+        };
+        if !age.is_changed() && !new_stems {
+            continue;
+        }
+        let growth = &spec.unwrap_or(&default_spec).growth;
+        let mut grown = Vec::new();
+        stems.traverse_mut::<AxisUp>([root]).track_self().for_each(
+            |(parent_entity, _, parent, ..), _, (entity, stem, this, delay, organ, dormant), _| {
+                let Some(this) = this else {
+                    return;
+                };
+                if organ.map(|organ| organ.plant) != Some(plant.0) {
+                    return;
+                }
+                // The node a daughter roots at emerged on its mother's timeline.
+                let parent_emergence = match parent {
+                    Some(parent) if *parent_entity != root => parent.emergence,
+                    _ => 0.0,
+                };
+                this.emergence = parent_emergence + delay.map_or(0.0, |delay| delay.0);
+                let factor = growth.growth(age.days - this.emergence);
+                stem.length = this.length * factor;
+                stem.size = this.size * factor;
+                match (age.days <= this.emergence, *dormant) {
+                    (true, false) => {
+                        commands.entity(*entity).insert(Dormant);
+                    }
+                    (false, true) => {
+                        commands.entity(*entity).remove::<Dormant>();
+                    }
+                    _ => {}
+                }
+                grown.push((*entity, this.emergence, factor));
+            },
+        );
+
+        // The flower at the tip of a pedicel grows with it and opens once the pedicel has
+        // grown, then sets fruit which ripens.
+        for (entity, emergence, factor) in grown {
+            let Ok((mut flower, fruit)) = flowers.get_mut(entity) else {
+                continue;
+            };
+            if flower.growth != factor {
+                flower.growth = factor;
+            }
+            let anthesis = emergence + growth.elongation_days;
+            let state = FlowerState::from_bloom((age.days - anthesis) / growth.fruit_set_days);
+            if flower.state != state {
+                flower.state = state;
+            }
+            if state != FlowerState::Fallen {
+                if fruit.is_some() {
+                    commands.entity(entity).remove::<Fruit>();
+                }
+                continue;
+            }
+            let maturity = (age.days - anthesis - growth.fruit_set_days) / growth.ripening_days;
+            let ripeness = Ripeness::from_maturity(maturity.min(1.0));
+            if fruit.map(|fruit| fruit.ripeness) != Some(ripeness) {
+                commands.entity(entity).insert(Fruit {
+                    shape: flower.fruit.clone(),
+                    ripeness,
+                });
+            }
+        }
+    }
+}
+
+/// Keeps the rest length of every constraint equal to the length of the stem it ends in.
+fn sync_rest_lengths(
+    mut constraints: Query<(&mut EdgeConstraint, Relations<P1>)>,
+    stems: Query<&Stem, Changed<Stem>>,
+) {
+    for (mut constraint, edges) in &mut constraints {
+        edges.join::<Up<P1>>(&stems).for_each(|stem| {
+            constraint.rest_length = stem.length;
+        });
+    }
+}
+
+pub(crate) fn growth_systems() -> impl IntoSystemConfigs<()> {
+    (
+        advance_plant_age,
+        age_daughters,
+        apply_deferred,
+        grow_plants,
+        sync_rest_lengths,
+    )
+        .chain()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_utils::{spawn_plant, test_app},
+        MeshMap, ParticlePosition, PlantMeshBuilder,
+    };
+    use bevy::{ecs::system::RunSystemOnce, utils::HashMap};
+
+    fn total_length(app: &mut App) -> f32 {
+        app.world
+            .run_system_once(|stems: Query<&Stem, With<Growth>>| {
+                stems.iter().map(|stem| stem.length).sum::<f32>()
+            })
+    }
+
+    fn dormant_stems(app: &mut App) -> usize {
+        app.world
+            .run_system_once(|stems: Query<(), With<Dormant>>| stems.iter().count())
+    }
+
+    fn face_count(app: &mut App) -> usize {
+        app.world.run_system_once(|meshes: PlantMeshBuilder| {
+            meshes
+                .build()
+                .values()
+                .map(|mesh| mesh.face_iter().count())
+                .sum()
+        })
+    }
+
+    #[test]
+    fn test_growing_keeps_the_particles_where_they_are() {
+        let mut app = test_app();
+        let plant = spawn_plant(&mut app, (PlantSpec::default(), PlantAge::new(40.0)));
+        let positions = |app: &mut App| {
+            app.world.run_system_once(
+                |particles: Query<(Entity, &ParticlePosition), With<Stem>>| {
+                    particles
+                        .iter()
+                        .map(|(entity, position)| (entity, position.0))
+                        .collect::<HashMap<_, _>>()
+                },
+            )
+        };
+        let before = positions(&mut app);
+
+        app.world.get_mut::<PlantAge>(plant).unwrap().days = 60.0;
+        app.update();
+        assert_eq!(positions(&mut app), before);
+    }
+
+    #[test]
+    fn test_plant_grows_with_age() {
+        let mut app = test_app();
+        let plant = spawn_plant(&mut app, (PlantSpec::default(), PlantAge::new(5.0)));
+        let young = total_length(&mut app);
+
+        // Buds grow with their pedicels, so they are not drawn before the trusses emerge.
+        let bud_sizes = app
+            .world
+            .run_system_once(|flowers: Query<(&Flower, &Transform)>| {
+                flowers
+                    .iter()
+                    .map(|(flower, transform)| {
+                        let mut mesh = MeshMap::default();
+                        flower.add_mesh(&mut mesh, transform);
+                        mesh.vertex_iter()
+                            .map(|vertex| {
+                                transform
+                                    .translation
+                                    .distance(mesh.vertex_position(vertex).into())
+                            })
+                            .fold(0.0, f32::max)
+                    })
+                    .collect::<Vec<_>>()
+            });
+        assert!(!bud_sizes.is_empty());
+        assert!(bud_sizes.iter().all(|size| *size < 0.01));
+        // Nor are the organs that have not emerged.
+        let dormant = dormant_stems(&mut app);
+        assert!(dormant > 0);
+        let young_faces = face_count(&mut app);
+
+        app.world.get_mut::<PlantAge>(plant).unwrap().days = 100.0;
+        app.update();
+        let old = total_length(&mut app);
+        assert!(young < old);
+        assert!(dormant_stems(&mut app) < dormant);
+        assert!(young_faces < face_count(&mut app));
+
+        let rest_lengths_match = app.world.run_system_once(
+            |constraints: Query<(&EdgeConstraint, Relations<P1>)>, stems: Query<&Stem>| {
+                constraints.iter().all(|(constraint, edges)| {
+                    let mut matches = true;
+                    edges.join::<Up<P1>>(&stems).for_each(|stem| {
+                        matches &= constraint.rest_length == stem.length;
+                    });
+                    matches
+                })
+            },
+        );
+        assert!(rest_lengths_match);
+    }
+}
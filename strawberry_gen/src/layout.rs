@@ -0,0 +1,311 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{PlantSpec, SizeDistribution, StrawberryPlant};
+
+/// How the plants of a scene are set out. Rows run along the Z axis and are laid side by side
+/// along X.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub enum PlantingPattern {
+    /// Raised soil beds, each planted with `rows` rows.
+    RaisedBeds {
+        beds: u32,
+        /// Distance between the centres of neighbouring beds.
+        bed_spacing: f32,
+        /// Rows on each bed, one or two in most fields.
+        rows: u32,
+        row_spacing: f32,
+        /// Whether neighbouring rows are offset by half a plant spacing.
+        staggered: bool,
+        /// Height of the bed top above the ground.
+        height: f32,
+    },
+    /// Gutters on tabletop stands, each holding a single row of plants. The trusses are trained
+    /// to hang down over the gutter edges.
+    TabletopGutters {
+        gutters: u32,
+        gutter_spacing: f32,
+        width: f32,
+        /// Height of the substrate surface in the gutters above the ground.
+        height: f32,
+    },
+    /// A grid of pots, one plant each.
+    Pots {
+        rows: u32,
+        row_spacing: f32,
+        /// Height of the substrate surface in the pots above the ground.
+        height: f32,
+    },
+    /// Plants dropped at random over a rectangle of the ground, no closer than
+    /// `plant_spacing` where possible.
+    Scatter { count: u32, size: Vec2 },
+}
+
+/// Generates the placement of all plants of a scene on the ground plane.
+#[derive(Resource, Debug, Clone, PartialEq, Reflect)]
+pub struct SceneLayout {
+    pub pattern: PlantingPattern,
+    /// Number of plants along each row, gutter or row of pots. Scatter does not use it.
+    pub plants_per_row: u32,
+    /// Distance between neighbouring plants along a row.
+    pub plant_spacing: f32,
+    /// Random offset of every plant from its planting position, relative to `plant_spacing`.
+    pub jitter: f32,
+    /// Seed of the jitter, the plant orientations and the seeds of the plants.
+    pub seed: u64,
+}
+impl Default for SceneLayout {
+    fn default() -> Self {
+        Self {
+            pattern: PlantingPattern::RaisedBeds {
+                beds: 1,
+                bed_spacing: 12.0,
+                rows: 2,
+                row_spacing: 4.0,
+                staggered: true,
+                height: 0.0,
+            },
+            plants_per_row: 3,
+            plant_spacing: 4.0,
+            jitter: 0.1,
+            seed: 0,
+        }
+    }
+}
+impl SceneLayout {
+    pub fn new(pattern: PlantingPattern) -> Self {
+        Self {
+            pattern,
+            ..default()
+        }
+    }
+    pub fn with_plants_per_row(mut self, plants: u32) -> Self {
+        self.plants_per_row = plants;
+        self
+    }
+    pub fn with_plant_spacing(mut self, spacing: f32) -> Self {
+        self.plant_spacing = spacing;
+        self
+    }
+    pub fn with_jitter(mut self, jitter: f32) -> Self {
+        self.jitter = jitter;
+        self
+    }
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Planting positions before jitter, centred on the origin.
+    fn planting_positions(&self, rng: &mut ChaCha8Rng) -> Vec<Vec3> {
+        let along = |plants: u32, offset: f32| {
+            let spacing = self.plant_spacing;
+            (0..plants).map(move |p| (p as f32 - (plants as f32 - 1.0) / 2.0 + offset) * spacing)
+        };
+        match self.pattern {
+            PlantingPattern::RaisedBeds {
+                beds,
+                bed_spacing,
+                rows,
+                row_spacing,
+                staggered,
+                height,
+            } => (0..beds)
+                .flat_map(|b| (0..rows).map(move |r| (b, r)))
+                .flat_map(|(b, r)| {
+                    let x = centred(beds, bed_spacing, b) + centred(rows, row_spacing, r);
+                    let offset = match (staggered, r % 2) {
+                        (false, _) => 0.0,
+                        (true, 0) => -0.25,
+                        (true, _) => 0.25,
+                    };
+                    along(self.plants_per_row, offset).map(move |z| Vec3::new(x, height, z))
+                })
+                .collect(),
+            PlantingPattern::TabletopGutters {
+                gutters,
+                gutter_spacing,
+                height,
+                ..
+            } => (0..gutters)
+                .flat_map(|g| {
+                    let x = centred(gutters, gutter_spacing, g);
+                    along(self.plants_per_row, 0.0).map(move |z| Vec3::new(x, height, z))
+                })
+                .collect(),
+            PlantingPattern::Pots {
+                rows,
+                row_spacing,
+                height,
+            } => (0..rows)
+                .flat_map(|r| {
+                    let x = centred(rows, row_spacing, r);
+                    along(self.plants_per_row, 0.0).map(move |z| Vec3::new(x, height, z))
+                })
+                .collect(),
+            PlantingPattern::Scatter { count, size } => {
+                let mut positions: Vec<Vec3> = Vec::new();
+                for _ in 0..count {
+                    // Keep the candidate furthest from the plants so far out of a few tries.
+                    let candidate = (0..8)
+                        .map(|_| {
+                            let x = rng.gen_range(-0.5..=0.5) * size.x;
+                            let z = rng.gen_range(-0.5..=0.5) * size.y;
+                            Vec3::new(x, 0.0, z)
+                        })
+                        .map(|candidate| {
+                            let distance = positions
+                                .iter()
+                                .map(|position| position.distance(candidate))
+                                .fold(f32::INFINITY, f32::min);
+                            (candidate, distance.min(self.plant_spacing))
+                        })
+                        .fold((Vec3::ZERO, -1.0), |best, candidate| {
+                            if candidate.1 > best.1 {
+                                candidate
+                            } else {
+                                best
+                            }
+                        });
+                    positions.push(candidate.0);
+                }
+                positions
+            }
+        }
+    }
+
+    /// Ground covered by every raised bed or gutter, in the XZ plane, with the height of its
+    /// top. Other patterns have none.
+    pub fn footprints(&self) -> Vec<(Rect, f32)> {
+        let length = self.plants_per_row as f32 * self.plant_spacing;
+        let footprint = |x: f32, width: f32, height: f32| {
+            let size = Vec2::new(width, length);
+            (Rect::from_center_size(Vec2::new(x, 0.0), size), height)
+        };
+        match self.pattern {
+            PlantingPattern::RaisedBeds {
+                beds,
+                bed_spacing,
+                rows,
+                row_spacing,
+                height,
+                ..
+            } => (0..beds)
+                .map(|b| {
+                    let x = centred(beds, bed_spacing, b);
+                    footprint(x, rows as f32 * row_spacing, height)
+                })
+                .collect(),
+            PlantingPattern::TabletopGutters {
+                gutters,
+                gutter_spacing,
+                width,
+                height,
+            } => (0..gutters)
+                .map(|g| footprint(centred(gutters, gutter_spacing, g), width, height))
+                .collect(),
+            PlantingPattern::Pots { .. } | PlantingPattern::Scatter { .. } => Vec::new(),
+        }
+    }
+
+    /// Position and orientation of every plant, turned by a random angle about the vertical.
+    pub fn placements(&self) -> Vec<Transform> {
+        let rng = &mut ChaCha8Rng::seed_from_u64(self.seed);
+        self.planting_positions(rng)
+            .into_iter()
+            .map(|position| {
+                let offset = Vec2::from_angle(rng.gen_range(0.0..TAU))
+                    * rng.gen_range(0.0..=1.0f32).sqrt()
+                    * self.jitter
+                    * self.plant_spacing;
+                Transform::from_translation(position + Vec3::new(offset.x, 0.0, offset.y))
+                    .with_rotation(Quat::from_rotation_y(rng.gen_range(0.0..TAU)))
+            })
+            .collect()
+    }
+
+    /// Size of the ground plane under all plants, with a margin of one plant spacing.
+    pub fn ground_size(&self) -> Vec2 {
+        let (min, max) = self.placements().iter().fold(
+            (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+            |(min, max), placement| {
+                let position = placement.translation.xz();
+                (min.min(position), max.max(position))
+            },
+        );
+        let size = (max - min).max(Vec2::ZERO);
+        let size = match self.pattern {
+            PlantingPattern::Scatter { size: area, .. } => size.max(area),
+            _ => size,
+        };
+        size + Vec2::splat(2.0 * self.plant_spacing)
+    }
+
+    /// Spawns a [`StrawberryPlant`] at every placement, each drawn from `spec` with its own
+    /// seed.
+    pub fn spawn(&self, commands: &mut Commands, spec: &PlantSpec) -> Vec<Entity> {
+        let rng = &mut ChaCha8Rng::seed_from_u64(self.seed.wrapping_add(1));
+        let mut spec = spec.clone();
+        if let PlantingPattern::TabletopGutters { .. } = self.pattern {
+            // Arch the trusses out and down, so the fruits hang over the gutter edges.
+            spec.rosette.truss_elevation = SizeDistribution::new(2.2, 0.2);
+        }
+        self.placements()
+            .into_iter()
+            .enumerate()
+            .map(|(i, transform)| {
+                let spec = PlantSpec {
+                    seed: rng.gen(),
+                    ..spec.clone()
+                };
+                commands
+                    .spawn((
+                        Name::new(format!("Plant {i}")),
+                        StrawberryPlant,
+                        spec,
+                        transform,
+                    ))
+                    .id()
+            })
+            .collect()
+    }
+}
+
+/// Offset of the `i`th of `count` items `spacing` apart, centred on zero.
+fn centred(count: u32, spacing: f32, i: u32) -> f32 {
+    (i as f32 - (count as f32 - 1.0) / 2.0) * spacing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_staggered_double_row_bed() {
+        let layout = SceneLayout::new(PlantingPattern::RaisedBeds {
+            beds: 2,
+            bed_spacing: 10.0,
+            rows: 2,
+            row_spacing: 3.0,
+            staggered: true,
+            height: 0.5,
+        })
+        .with_plants_per_row(5)
+        .with_jitter(0.0);
+        let placements = layout.placements();
+        assert_eq!(placements.len(), 20);
+        assert!(placements.iter().all(|p| p.translation.y == 0.5));
+        // Odd rows are shifted by half a spacing along the row.
+        let first_row = placements[0].translation.z;
+        let second_row = placements[5].translation.z;
+        assert_eq!(second_row - first_row, 0.5 * layout.plant_spacing);
+        let ground = layout.ground_size();
+        assert!(placements.iter().all(|p| {
+            p.translation.x.abs() < ground.x / 2.0 && p.translation.z.abs() < ground.y / 2.0
+        }));
+        assert_eq!(placements, layout.placements());
+    }
+}
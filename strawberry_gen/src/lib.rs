@@ -1,14 +1,56 @@
 mod constraints;
 pub use constraints::*;
 
+mod organs;
+pub use organs::*;
+
+mod organ;
+pub use organ::*;
+
 mod mesh_map;
 pub use mesh_map::*;
 
+mod mesh_io;
+pub use mesh_io::*;
+
+mod gltf;
+pub use gltf::*;
+
+mod plant_spec;
+pub use plant_spec::*;
+
+mod cultivar;
+pub use cultivar::*;
+
 mod plant_gen;
 pub use plant_gen::*;
 
+mod plant_mesh;
+pub use plant_mesh::*;
+
+mod growth;
+pub use growth::*;
+
+mod tropism;
+pub use tropism::*;
+
+mod layout;
+pub use layout::*;
+
+mod environment;
+pub use environment::*;
+
+mod skeleton;
+pub use skeleton::*;
+
+mod grammar;
+pub use grammar::*;
+
 mod physics;
 pub use physics::*;
 
 mod viz_app;
-pub use viz_app::*;
\ No newline at end of file
+pub use viz_app::*;
+
+#[cfg(test)]
+mod test_utils;
@@ -1,4 +1,4 @@
-use strawberry_gen::{StrawberryPlant, viz_plant};
+use strawberry_gen::{viz_plant, StrawberryPlant};
 
 fn main() {
     viz_plant();
@@ -0,0 +1,634 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use bevy::prelude::*;
+
+use crate::{DefectKinds, FaceLabel, MeshMap, Organ, OrganId, OrganKind, Prop, VertexId};
+
+/// What [`MeshMap::write_obj`] and [`MeshMap::write_ply`] write besides the positions, UVs and
+/// normals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExportOptions {
+    /// Vertex colors, in sRGB.
+    pub colors: bool,
+    /// The organ or prop of every face and the kinds of the defects on it: OBJ groups named like
+    /// `Fruit_00c0ffee00c0ffee` or `Mulch`, followed by a group for every defect kind, like
+    /// `BirdPeck`, or the PLY face properties `organ_kind`, `organ_id_high`, `organ_id_low`,
+    /// `organ_defects`, holding [`DefectKinds::bits`], and `prop`.
+    pub labels: bool,
+}
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            colors: true,
+            labels: true,
+        }
+    }
+}
+impl ExportOptions {
+    pub fn with_colors(mut self, colors: bool) -> Self {
+        self.colors = colors;
+        self
+    }
+    pub fn with_labels(mut self, labels: bool) -> Self {
+        self.labels = labels;
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum MeshIoError {
+    Io(io::Error),
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// A valid file using a feature the readers do not support, like binary PLY.
+    Unsupported(String),
+}
+impl fmt::Display for MeshIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "cannot read or write mesh: {error}"),
+            Self::Parse { line, message } => write!(f, "line {line}: {message}"),
+            Self::Unsupported(message) => write!(f, "unsupported mesh file: {message}"),
+        }
+    }
+}
+impl std::error::Error for MeshIoError {}
+impl From<io::Error> for MeshIoError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+fn srgb(color: [f32; 4]) -> [f32; 4] {
+    Color::rgba_linear(color[0], color[1], color[2], color[3]).as_rgba_f32()
+}
+
+fn linear(color: [f32; 4]) -> [f32; 4] {
+    Color::rgba(color[0], color[1], color[2], color[3]).as_linear_rgba_f32()
+}
+
+/// Name of the organ or prop a face label stands for, used for OBJ groups and glTF meshes.
+pub(crate) fn group_name(label: Option<FaceLabel>) -> String {
+    match label {
+        Some(FaceLabel::Organ(organ)) => format!("{:?}_{:016x}", organ.kind, organ.id.0),
+        Some(FaceLabel::Prop(prop)) => format!("{prop:?}"),
+        None => "default".into(),
+    }
+}
+
+/// The label of faces read from a file. The plant they belonged to is not stored, so it is a
+/// placeholder.
+fn read_label(kind: OrganKind, id: u64) -> Organ {
+    Organ {
+        kind,
+        id: OrganId(id),
+        plant: Entity::PLACEHOLDER,
+        defects: DefectKinds::default(),
+    }
+}
+
+/// The label of the OBJ group names of a line, the first naming the organ or prop and the
+/// others the kinds of the defects on it.
+fn parse_group_names(names: &[&str]) -> Option<FaceLabel> {
+    let (name, defects) = names.split_first()?;
+    let Some((kind, id)) = name.split_once('_') else {
+        return name.parse::<Prop>().ok().map(FaceLabel::Prop);
+    };
+    let organ = read_label(kind.parse().ok()?, u64::from_str_radix(id, 16).ok()?);
+    let defects = defects
+        .iter()
+        .filter_map(|name| name.parse().ok())
+        .collect();
+    Some(organ.with_defects(defects).into())
+}
+
+/// An element declared in the header of a PLY file.
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+/// A property of a [`PlyElement`], with its declared type, or the type of its items if it is a
+/// list.
+struct PlyProperty {
+    name: String,
+    kind: String,
+    list: bool,
+}
+impl PlyProperty {
+    /// Value of the full intensity of a color channel stored in this property.
+    fn color_scale(&self) -> f64 {
+        match self.kind.as_str() {
+            "char" | "int8" => i8::MAX.into(),
+            "uchar" | "uint8" => u8::MAX.into(),
+            "short" | "int16" => i16::MAX.into(),
+            "ushort" | "uint16" => u16::MAX.into(),
+            "int" | "int32" => i32::MAX.into(),
+            "uint" | "uint32" => u32::MAX.into(),
+            _ => 1.0,
+        }
+    }
+}
+
+impl MeshMap {
+    /// Writes the mesh to `path` as OBJ or ASCII PLY, depending on its extension.
+    pub fn save(&self, path: impl AsRef<Path>, options: &ExportOptions) -> Result<(), MeshIoError> {
+        let path = path.as_ref();
+        let mut writer = BufWriter::new(File::create(path)?);
+        match extension(path).as_deref() {
+            Some("obj") => self.write_obj(&mut writer, options)?,
+            Some("ply") => self.write_ply(&mut writer, options)?,
+            _ => return Err(unsupported_extension(path)),
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads an OBJ or ASCII PLY file, depending on its extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MeshIoError> {
+        let path = path.as_ref();
+        let reader = BufReader::new(File::open(path)?);
+        match extension(path).as_deref() {
+            Some("obj") => Self::read_obj(reader),
+            Some("ply") => Self::read_ply(reader),
+            _ => Err(unsupported_extension(path)),
+        }
+    }
+
+    /// Writes the mesh as Wavefront OBJ. Vertex colors follow the position of each vertex, as
+    /// most tools expect.
+    pub fn write_obj(&self, writer: &mut impl Write, options: &ExportOptions) -> io::Result<()> {
+        for vertex in self.vertex_iter() {
+            let [x, y, z] = self.vertex_position(vertex);
+            write!(writer, "v {x} {y} {z}")?;
+            if options.colors {
+                let [r, g, b, _] = srgb(self.vertex_color(vertex));
+                write!(writer, " {r} {g} {b}")?;
+            }
+            writeln!(writer)?;
+        }
+        for vertex in self.vertex_iter() {
+            let [u, v] = self.vertex_uv(vertex);
+            writeln!(writer, "vt {u} {v}")?;
+        }
+        for vertex in self.vertex_iter() {
+            let [x, y, z] = self.vertex_normal(vertex);
+            writeln!(writer, "vn {x} {y} {z}")?;
+        }
+        let mut group = None;
+        for face in self.face_iter() {
+            if options.labels {
+                let label = self.face_label(face);
+                let mut name = group_name(label);
+                let defects = label.and_then(FaceLabel::organ).map(|organ| organ.defects);
+                for defect in defects.unwrap_or_default().iter() {
+                    name += &format!(" {defect:?}");
+                }
+                if group.as_ref() != Some(&name) {
+                    writeln!(writer, "g {name}")?;
+                    group = Some(name);
+                }
+            }
+            let [a, b, c] = self.face_vertices(face).map(|vertex| *vertex + 1);
+            writeln!(writer, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+        Ok(())
+    }
+
+    /// Reads a Wavefront OBJ mesh. Polygons are split into triangle fans, and a vertex is made
+    /// for every distinct combination of position, UV and normal the faces use. Faces in a
+    /// group named like a label written by [`write_obj`](Self::write_obj) get that label,
+    /// with a placeholder plant, and the defect kinds named by the other groups of the line.
+    pub fn read_obj(reader: impl BufRead) -> Result<Self, MeshIoError> {
+        let mut mesh = Self::default();
+        let mut positions = Vec::new();
+        let mut colors = Vec::new();
+        let mut uvs = Vec::new();
+        let mut normals = Vec::new();
+        let mut vertices = HashMap::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let error = |message: String| MeshIoError::Parse {
+                line: index + 1,
+                message,
+            };
+            let mut fields = line.split_whitespace();
+            let Some(keyword) = fields.next() else {
+                continue;
+            };
+            let fields = fields.collect::<Vec<_>>();
+            let numbers = || {
+                fields
+                    .iter()
+                    .map(|field| {
+                        field
+                            .parse::<f32>()
+                            .map_err(|_| error(format!("invalid number `{field}`")))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            };
+            match keyword {
+                "v" => {
+                    let numbers = numbers()?;
+                    if numbers.len() < 3 {
+                        return Err(error("a vertex needs 3 coordinates".into()));
+                    }
+                    positions.push([numbers[0], numbers[1], numbers[2]]);
+                    colors.push(match numbers.get(3..6) {
+                        Some(&[r, g, b]) => linear([r, g, b, 1.0]),
+                        _ => [1.0; 4],
+                    });
+                }
+                "vt" => {
+                    let numbers = numbers()?;
+                    uvs.push([
+                        numbers.first().copied().unwrap_or_default(),
+                        numbers.get(1).copied().unwrap_or_default(),
+                    ]);
+                }
+                "vn" => {
+                    let numbers = numbers()?;
+                    if numbers.len() < 3 {
+                        return Err(error("a normal needs 3 coordinates".into()));
+                    }
+                    normals.push([numbers[0], numbers[1], numbers[2]]);
+                }
+                "g" => {
+                    mesh.set_label(parse_group_names(&fields));
+                }
+                "f" => {
+                    let mut corners = Vec::new();
+                    for field in &fields {
+                        let mut indices = field.split('/');
+                        let mut index = |count: usize| -> Result<Option<usize>, MeshIoError> {
+                            match indices.next().filter(|index| !index.is_empty()) {
+                                None => Ok(None),
+                                Some(index) => resolve_index(index, count)
+                                    .map(Some)
+                                    .ok_or_else(|| error(format!("invalid index in `{field}`"))),
+                            }
+                        };
+                        let position = index(positions.len())?
+                            .ok_or_else(|| error(format!("missing position in `{field}`")))?;
+                        let key = (position, index(uvs.len())?, index(normals.len())?);
+                        let vertex = *vertices.entry(key).or_insert_with(|| {
+                            let vertex = mesh.add_vertex(positions[key.0]);
+                            mesh.set_color(vertex, colors[key.0]);
+                            if let Some(uv) = key.1 {
+                                mesh.set_uv(vertex, uvs[uv]);
+                            }
+                            if let Some(normal) = key.2 {
+                                mesh.set_normal(vertex, normals[normal]);
+                            }
+                            vertex
+                        });
+                        corners.push(vertex);
+                    }
+                    add_polygon(&mut mesh, &corners).map_err(error)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(mesh)
+    }
+
+    /// Writes the mesh as ASCII PLY. Colors are written as bytes, like most tools expect.
+    pub fn write_ply(&self, writer: &mut impl Write, options: &ExportOptions) -> io::Result<()> {
+        writeln!(writer, "ply")?;
+        writeln!(writer, "format ascii 1.0")?;
+        writeln!(writer, "element vertex {}", self.vertex_iter().count())?;
+        for property in ["x", "y", "z", "nx", "ny", "nz", "s", "t"] {
+            writeln!(writer, "property float {property}")?;
+        }
+        if options.colors {
+            for property in ["red", "green", "blue", "alpha"] {
+                writeln!(writer, "property uchar {property}")?;
+            }
+        }
+        writeln!(writer, "element face {}", self.face_iter().count())?;
+        writeln!(writer, "property list uchar uint vertex_indices")?;
+        if options.labels {
+            writeln!(writer, "property int organ_kind")?;
+            writeln!(writer, "property uint organ_id_high")?;
+            writeln!(writer, "property uint organ_id_low")?;
+            writeln!(writer, "property uint organ_defects")?;
+            writeln!(writer, "property int prop")?;
+        }
+        writeln!(writer, "end_header")?;
+
+        for vertex in self.vertex_iter() {
+            let [x, y, z] = self.vertex_position(vertex);
+            let [nx, ny, nz] = self.vertex_normal(vertex);
+            let [s, t] = self.vertex_uv(vertex);
+            write!(writer, "{x} {y} {z} {nx} {ny} {nz} {s} {t}")?;
+            if options.colors {
+                let [r, g, b, a] = srgb(self.vertex_color(vertex))
+                    .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+                write!(writer, " {r} {g} {b} {a}")?;
+            }
+            writeln!(writer)?;
+        }
+        for face in self.face_iter() {
+            let [a, b, c] = self.face_vertices(face);
+            write!(writer, "3 {} {} {}", *a, *b, *c)?;
+            if options.labels {
+                let (kind, id, defects, prop) = match self.face_label(face) {
+                    Some(FaceLabel::Organ(organ)) => {
+                        (organ.kind as i32, organ.id.0, organ.defects.bits(), -1)
+                    }
+                    Some(FaceLabel::Prop(prop)) => (-1, 0, 0, prop as i32),
+                    None => (-1, 0, 0, -1),
+                };
+                write!(
+                    writer,
+                    " {kind} {} {} {defects} {prop}",
+                    id >> 32,
+                    id as u32
+                )?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Reads an ASCII PLY mesh with the vertex properties [`write_ply`](Self::write_ply)
+    /// writes, skipping any others and any other elements. Texture coordinates may also be
+    /// named `u` and `v`, and colors may be floats. Polygons are split into triangle fans.
+    pub fn read_ply(reader: impl BufRead) -> Result<Self, MeshIoError> {
+        let mut lines = reader.lines().enumerate();
+        let mut next_line = || -> Result<(usize, String), MeshIoError> {
+            match lines.next() {
+                Some((index, line)) => Ok((index + 1, line?)),
+                None => Err(MeshIoError::Unsupported("the file ends early".into())),
+            }
+        };
+
+        let mut elements = Vec::<PlyElement>::new();
+        let (_, magic) = next_line()?;
+        if magic.trim() != "ply" {
+            return Err(MeshIoError::Unsupported("not a PLY file".into()));
+        }
+        loop {
+            let (line, text) = next_line()?;
+            let error = |message: String| MeshIoError::Parse { line, message };
+            let fields = text.split_whitespace().collect::<Vec<_>>();
+            match fields.as_slice() {
+                ["format", "ascii", _] => {}
+                ["format", format, _] => {
+                    return Err(MeshIoError::Unsupported(format!("{format} PLY")));
+                }
+                ["element", name, count] => {
+                    let count = count
+                        .parse()
+                        .map_err(|_| error(format!("invalid count `{count}`")))?;
+                    elements.push(PlyElement {
+                        name: name.to_string(),
+                        count,
+                        properties: Vec::new(),
+                    });
+                }
+                ["property", "list", _, kind, name] | ["property", kind, name] => {
+                    let element = elements
+                        .last_mut()
+                        .ok_or_else(|| error("property outside an element".into()))?;
+                    element.properties.push(PlyProperty {
+                        name: name.to_string(),
+                        kind: kind.to_string(),
+                        list: fields[1] == "list",
+                    });
+                }
+                ["end_header"] => break,
+                _ => {}
+            }
+        }
+
+        let mut mesh = Self::default();
+        for element in &elements {
+            let vertex_count = mesh.vertex_iter().count();
+            for _ in 0..element.count {
+                let (line, text) = next_line()?;
+                let error = |message: String| MeshIoError::Parse { line, message };
+                let mut values = text.split_whitespace();
+                let mut number = || -> Result<f64, MeshIoError> {
+                    let value = values
+                        .next()
+                        .ok_or_else(|| error("too few values".into()))?;
+                    value
+                        .parse()
+                        .map_err(|_| error(format!("invalid number `{value}`")))
+                };
+                let mut scalars = HashMap::new();
+                let mut list = Vec::new();
+                for property in &element.properties {
+                    if property.list {
+                        let length = number()? as usize;
+                        let items = (0..length)
+                            .map(|_| number())
+                            .collect::<Result<Vec<_>, _>>()?;
+                        if matches!(property.name.as_str(), "vertex_indices" | "vertex_index") {
+                            list = items;
+                        }
+                    } else {
+                        scalars.insert(property.name.as_str(), (number()?, property));
+                    }
+                }
+                let get = |names: &[&str], default: f64| {
+                    names
+                        .iter()
+                        .find_map(|name| scalars.get(name))
+                        .map_or(default, |(value, _)| *value) as f32
+                };
+                // Labels are integers too large for an `f32`.
+                let integer = |name: &str| scalars.get(name).map(|(value, _)| *value);
+                // Colors stored as integers span the range of their type.
+                let channel = |name: &str| {
+                    scalars
+                        .get(name)
+                        .map_or(1.0, |(value, property)| value / property.color_scale())
+                        as f32
+                };
+                match element.name.as_str() {
+                    "vertex" => {
+                        let vertex =
+                            mesh.add_vertex([get(&["x"], 0.0), get(&["y"], 0.0), get(&["z"], 0.0)]);
+                        mesh.set_normal(
+                            vertex,
+                            [get(&["nx"], 0.0), get(&["ny"], 0.0), get(&["nz"], 0.0)],
+                        );
+                        mesh.set_uv(vertex, [get(&["s", "u"], 0.0), get(&["t", "v"], 0.0)]);
+                        mesh.set_color(
+                            vertex,
+                            linear([
+                                channel("red"),
+                                channel("green"),
+                                channel("blue"),
+                                channel("alpha"),
+                            ]),
+                        );
+                    }
+                    "face" => {
+                        let kind = integer("organ_kind").unwrap_or(-1.0);
+                        let label = usize::try_from(kind as i64)
+                            .ok()
+                            .and_then(|kind| OrganKind::ALL.get(kind))
+                            .map(|kind| {
+                                let high = integer("organ_id_high").unwrap_or_default();
+                                let low = integer("organ_id_low").unwrap_or_default();
+                                read_label(*kind, (high as u64) << 32 | low as u64)
+                            });
+                        let defects = integer("organ_defects").unwrap_or_default();
+                        let defects = DefectKinds::from_bits(defects as u32);
+                        let prop = usize::try_from(integer("prop").unwrap_or(-1.0) as i64)
+                            .ok()
+                            .and_then(|prop| Prop::ALL.get(prop));
+                        mesh.set_label(match (label, prop) {
+                            (Some(organ), _) => Some(organ.with_defects(defects).into()),
+                            (None, Some(prop)) => Some((*prop).into()),
+                            (None, None) => None,
+                        });
+                        let corners = list
+                            .iter()
+                            .map(|index| {
+                                let index = *index as usize;
+                                (index < vertex_count)
+                                    .then(|| VertexId::from(index as u32))
+                                    .ok_or_else(|| error(format!("invalid vertex index {index}")))
+                            })
+                            .collect::<Result<Vec<_>, _>>()?;
+                        add_polygon(&mut mesh, &corners).map_err(error)?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        mesh.set_label(None);
+        Ok(mesh)
+    }
+}
+
+pub(crate) fn extension(path: &Path) -> Option<String> {
+    Some(path.extension()?.to_str()?.to_ascii_lowercase())
+}
+
+pub(crate) fn unsupported_extension(path: &Path) -> MeshIoError {
+    MeshIoError::Unsupported(format!("unknown mesh format of `{}`", path.display()))
+}
+
+/// Resolves a one-based OBJ index, or a negative one counting back from the last element.
+fn resolve_index(index: &str, count: usize) -> Option<usize> {
+    let index: i64 = index.parse().ok()?;
+    let index = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    (0..count as i64).contains(&index).then_some(index as usize)
+}
+
+/// Adds a polygon as a fan of triangles around its first corner.
+fn add_polygon(mesh: &mut MeshMap, corners: &[VertexId]) -> Result<(), String> {
+    if corners.len() < 3 {
+        return Err(format!("a face needs 3 corners, found {}", corners.len()));
+    }
+    for pair in corners[1..].windows(2) {
+        mesh.add_face([corners[0], pair[0], pair[1]]);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_obj_and_ply_round_trip() {
+        let mut mesh = MeshMap::default();
+        let corners = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.5],
+        ];
+        let vertices = corners.map(|corner| {
+            let vertex = mesh.add_vertex(corner);
+            mesh.set_uv(vertex, [corner[0], corner[1]]);
+            mesh.set_color(vertex, [corner[0], 0.5, corner[1], 1.0]);
+            vertex
+        });
+        let fruit = read_label(OrganKind::Fruit, 0x0123_4567_89ab_cdef);
+        mesh.set_label(Some(fruit.into()));
+        mesh.add_face([vertices[0], vertices[1], vertices[2]]);
+        mesh.set_label(None);
+        mesh.add_face([vertices[0], vertices[2], vertices[3]]);
+        mesh.update_normals();
+
+        let options = ExportOptions::default();
+        let mut obj = Vec::new();
+        mesh.write_obj(&mut obj, &options).unwrap();
+        let mut ply = Vec::new();
+        mesh.write_ply(&mut ply, &options).unwrap();
+        for read in [
+            MeshMap::read_obj(obj.as_slice()).unwrap(),
+            MeshMap::read_ply(ply.as_slice()).unwrap(),
+        ] {
+            assert_eq!(read.face_iter().count(), 2);
+            assert_eq!(read.face_label(0.into()), Some(fruit.into()));
+            assert_eq!(read.face_label(1.into()), None);
+            for face in read.face_iter() {
+                for (original, vertex) in mesh
+                    .face_vertices(face)
+                    .iter()
+                    .zip(read.face_vertices(face))
+                {
+                    assert_eq!(
+                        read.vertex_position(vertex),
+                        mesh.vertex_position(*original)
+                    );
+                    assert_eq!(read.vertex_uv(vertex), mesh.vertex_uv(*original));
+                    assert_eq!(read.vertex_normal(vertex), mesh.vertex_normal(*original));
+                    let color = Vec4::from(read.vertex_color(vertex));
+                    assert!(color.abs_diff_eq(mesh.vertex_color(*original).into(), 0.01));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ply_values_are_read_by_their_declared_type() {
+        let ply = "ply
+format ascii 1.0
+element vertex 3
+property float x
+property float y
+property float z
+property float red
+property ushort green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+property uchar organ_kind
+property uint organ_id_high
+property uint organ_id_low
+end_header
+0 0 0 1.0 65535 0
+1 0 0 1.0 65535 0
+0 1 0 1.0 65535 0
+3 0 1 2 3 0 7
+";
+        let mesh = MeshMap::read_ply(ply.as_bytes()).unwrap();
+        assert_eq!(mesh.vertex_color(0.into()), [1.0, 1.0, 0.0, 1.0]);
+        assert_eq!(
+            mesh.face_label(0.into()),
+            Some(read_label(OrganKind::ALL[3], 7).into())
+        );
+    }
+}
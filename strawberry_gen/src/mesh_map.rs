@@ -3,15 +3,31 @@ use std::ops::Deref;
 use bevy::{
     prelude::*,
     render::{mesh::Indices, render_resource::PrimitiveTopology},
+    utils::HashMap,
 };
 use iter_tools::Itertools;
 
+use crate::FaceLabel;
+
+/// A triangle mesh with per-vertex attributes and a label for every face.
+///
+/// The mesh keeps its adjacency up to date as vertices and faces are added. Every face has
+/// three half-edges running around it in the order of its vertices, the `k`th from vertex `k`
+/// to vertex `k + 1`, and the half-edge running the other way along the same edge is its twin.
+/// A half-edge without a twin lies on the boundary. Where more than two faces share an edge,
+/// only the first two added are twins.
 #[derive(Component, Debug, Default, Clone)]
 pub struct MeshMap {
     vertices: Vec<[f32; 3]>,
     faces: Vec<[VertexId; 3]>,
     normals: Vec<[f32; 3]>,
     uvs: Vec<[f32; 2]>,
+    colors: Vec<[f32; 4]>,
+    labels: Vec<Option<FaceLabel>>,
+    label: Option<FaceLabel>,
+    vertex_faces: Vec<Vec<FaceId>>,
+    twins: Vec<Option<HalfEdgeId>>,
+    half_edges: HashMap<(VertexId, VertexId), HalfEdgeId>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -42,6 +58,53 @@ impl Deref for FaceId {
     }
 }
 
+/// How the normals of the faces around a vertex are weighted in the vertex normal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum NormalWeighting {
+    /// Every face counts the same.
+    Uniform,
+    /// Faces count by their area.
+    Area,
+    /// Faces count by their angle at the vertex, so the normal does not depend on how the
+    /// surface is split into triangles.
+    #[default]
+    Angle,
+}
+
+/// How [`MeshMap::update_normals_with`] computes the vertex normals.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NormalOptions {
+    pub weighting: NormalWeighting,
+    /// Faces meeting at more than this angle, in radians, have a hard edge between them. The
+    /// mesh is smooth everywhere when `None`.
+    pub crease_angle: Option<f32>,
+}
+impl NormalOptions {
+    pub fn with_weighting(mut self, weighting: NormalWeighting) -> Self {
+        self.weighting = weighting;
+        self
+    }
+    pub fn with_crease_angle(mut self, crease_angle: f32) -> Self {
+        self.crease_angle = Some(crease_angle);
+        self
+    }
+}
+
+/// A directed edge of a face, see [`MeshMap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HalfEdgeId(u32);
+impl From<u32> for HalfEdgeId {
+    fn from(id: u32) -> Self {
+        Self(id)
+    }
+}
+impl Deref for HalfEdgeId {
+    type Target = u32;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 impl MeshMap {
     pub fn add_vertex<T: Into<[f32; 3]>>(&mut self, vertex: T) -> VertexId {
         let vertex = vertex.into();
@@ -49,13 +112,60 @@ impl MeshMap {
         self.vertices.push(vertex);
         self.normals.push([0.0, 0.0, 0.0]);
         self.uvs.push([0.0, 0.0]);
+        self.colors.push([1.0, 1.0, 1.0, 1.0]);
+        self.vertex_faces.push(Vec::new());
         index.into()
     }
     pub fn add_face<T: Into<[VertexId; 3]>>(&mut self, face: T) -> FaceId {
         let index = self.faces.len() as u32;
-        self.faces.push(face.into());
+        let vertices = face.into();
+        self.faces.push(vertices);
+        self.labels.push(self.label);
+        self.link_face(index.into());
         index.into()
     }
+    /// Adds `face` to the adjacency of its vertices and pairs its half-edges with their twins.
+    fn link_face(&mut self, face: FaceId) {
+        for vertex in self.face_vertices(face).iter().unique() {
+            self.vertex_faces[**vertex as usize].push(face);
+        }
+        for half_edge in self.face_half_edges(face) {
+            let (origin, target) = (
+                self.half_edge_origin(half_edge),
+                self.half_edge_target(half_edge),
+            );
+            let twin = self
+                .half_edges
+                .get(&(target, origin))
+                .copied()
+                .filter(|twin| self.twins[**twin as usize].is_none());
+            if let Some(twin) = twin {
+                self.twins[*twin as usize] = Some(half_edge);
+            }
+            self.twins.push(twin);
+            self.half_edges.entry((origin, target)).or_insert(half_edge);
+        }
+    }
+    /// Rebuilds the adjacency of all faces, after their vertices changed.
+    fn relink_faces(&mut self) {
+        self.vertex_faces = vec![Vec::new(); self.vertices.len()];
+        self.twins.clear();
+        self.half_edges.clear();
+        for face in self.face_iter() {
+            self.link_face(face);
+        }
+    }
+    /// Sets the organ or prop that faces added from now on show.
+    pub fn set_label(&mut self, label: Option<FaceLabel>) {
+        self.label = label;
+    }
+    /// The organ or prop that faces added from now on show.
+    pub fn label(&self) -> Option<FaceLabel> {
+        self.label
+    }
+    pub fn face_label(&self, face: FaceId) -> Option<FaceLabel> {
+        self.labels[*face as usize]
+    }
     pub fn vertex_iter(&self) -> impl Iterator<Item = VertexId> {
         (0..self.vertices.len() as u32).map(|i| i.into())
     }
@@ -95,39 +205,238 @@ impl MeshMap {
         ];
         normal
     }
-    pub fn compute_vertex_normal(&self, vertex: VertexId) -> [f32; 3] {
-        let mut normal = [0.0, 0.0, 0.0];
-        let mut count = 0;
+    /// The unit normal of `vertex`, from the normals of its faces weighted by `weighting`.
+    /// A vertex without faces, or only degenerate ones, points up the Y axis.
+    pub fn compute_vertex_normal(&self, vertex: VertexId, weighting: NormalWeighting) -> [f32; 3] {
+        self.vertex_faces(vertex)
+            .iter()
+            .map(|face| {
+                let corner = self.face_vertices(*face).iter().position(|v| *v == vertex);
+                self.corner_normal(*face, corner.unwrap_or_default(), weighting)
+            })
+            .sum::<Vec3>()
+            .try_normalize()
+            .unwrap_or(Vec3::Y)
+            .into()
+    }
+    /// Sets the normal of every vertex with the default [`NormalOptions`].
+    pub fn update_normals(&mut self) {
+        self.update_normals_with(&NormalOptions::default());
+    }
+    /// Sets the normal of every vertex to the unit weighted sum of the normals of its faces.
+    /// Vertices without faces, or only degenerate ones, point up the Y axis.
+    ///
+    /// With a crease angle, every vertex on a hard edge is split into one vertex for each
+    /// smooth patch of faces around it, with the same position, UV and color. The faces on
+    /// either side of a hard edge then no longer share it.
+    pub fn update_normals_with(&mut self, options: &NormalOptions) {
+        if let Some(crease_angle) = options.crease_angle {
+            self.split_creases(crease_angle);
+        }
+        let mut normals = vec![Vec3::ZERO; self.vertices.len()];
         for face in self.face_iter() {
-            let [a, b, c] = self.face_vertices(face);
-            if a == vertex || b == vertex || c == vertex {
-                let face_normal = self.compute_face_normal(face);
-                normal[0] += face_normal[0];
-                normal[1] += face_normal[1];
-                normal[2] += face_normal[2];
-                count += 1;
+            for (corner, vertex) in self.face_vertices(face).iter().enumerate() {
+                normals[**vertex as usize] += self.corner_normal(face, corner, options.weighting);
             }
         }
-        normal[0] /= count as f32;
-        normal[1] /= count as f32;
-        normal[2] /= count as f32;
-        normal
+        for (normal, sum) in self.normals.iter_mut().zip(normals) {
+            *normal = sum.try_normalize().unwrap_or(Vec3::Y).into();
+        }
     }
-    pub fn update_normals(&mut self) {
-        for vertex in self.vertex_iter() {
-            let normal = self.compute_vertex_normal(vertex);
-            self.normals[*vertex as usize] = normal;
+    /// The share of `face` in the normal of the vertex at `corner`.
+    fn corner_normal(&self, face: FaceId, corner: usize, weighting: NormalWeighting) -> Vec3 {
+        let normal = Vec3::from(self.compute_face_normal(face));
+        match weighting {
+            NormalWeighting::Uniform => normal.normalize_or_zero(),
+            NormalWeighting::Area => normal * 0.5,
+            NormalWeighting::Angle => {
+                let (a, b, c) = self.face_positions(face);
+                let corners = [Vec3::from(a), Vec3::from(b), Vec3::from(c)];
+                let [this, next, previous] = [0, 1, 2].map(|k| corners[(corner + k) % 3]);
+                let angle = (next - this).angle_between(previous - this);
+                normal.normalize_or_zero() * if angle.is_nan() { 0.0 } else { angle }
+            }
+        }
+    }
+    /// Splits every vertex on an edge between faces meeting at more than `crease_angle`, so
+    /// that each smooth patch of faces around it has its own copy of the vertex.
+    fn split_creases(&mut self, crease_angle: f32) {
+        let face_normals = self
+            .face_iter()
+            .map(|face| Vec3::from(self.compute_face_normal(face)).normalize_or_zero())
+            .collect_vec();
+        let smooth = |a: FaceId, b: FaceId| {
+            let (a, b) = (face_normals[*a as usize], face_normals[*b as usize]);
+            a.dot(b) >= crease_angle.cos() - 1e-6
+        };
+        for vertex in 0..self.vertices.len() as u32 {
+            let vertex = VertexId::from(vertex);
+            let faces = self.vertex_faces(vertex).to_vec();
+            if faces.len() < 2 {
+                continue;
+            }
+            // Join the faces around the vertex into patches across their smooth edges.
+            let mut patches = (0..faces.len()).collect_vec();
+            for (i, face) in faces.iter().enumerate() {
+                for half_edge in self.face_half_edges(*face) {
+                    let touches = self.half_edge_origin(half_edge) == vertex
+                        || self.half_edge_target(half_edge) == vertex;
+                    let Some(twin) = self.twin(half_edge).filter(|_| touches) else {
+                        continue;
+                    };
+                    let neighbour = self.half_edge_face(twin);
+                    let Some(j) = faces.iter().position(|face| *face == neighbour) else {
+                        continue;
+                    };
+                    if smooth(*face, neighbour) {
+                        let (a, b) = (find_patch(&mut patches, i), find_patch(&mut patches, j));
+                        patches[a] = b;
+                    }
+                }
+            }
+            // The first patch keeps the vertex, the others get copies.
+            let mut copies = HashMap::new();
+            let first = find_patch(&mut patches, 0);
+            for (i, face) in faces.iter().enumerate() {
+                let patch = find_patch(&mut patches, i);
+                if patch == first {
+                    continue;
+                }
+                let copy = *copies.entry(patch).or_insert_with(|| {
+                    let copy = self.add_vertex(self.vertex_position(vertex));
+                    self.set_uv(copy, self.uvs[*vertex as usize]);
+                    self.set_color(copy, self.vertex_color(vertex));
+                    copy
+                });
+                for corner in self.faces[**face as usize].iter_mut() {
+                    if *corner == vertex {
+                        *corner = copy;
+                    }
+                }
+            }
         }
+        self.relink_faces();
+    }
+
+    /// The faces `vertex` is a corner of, in the order they were added.
+    pub fn vertex_faces(&self, vertex: VertexId) -> &[FaceId] {
+        &self.vertex_faces[*vertex as usize]
+    }
+    /// The vertices sharing an edge with `vertex`, each once.
+    pub fn vertex_neighbours(&self, vertex: VertexId) -> impl Iterator<Item = VertexId> + '_ {
+        self.vertex_faces(vertex)
+            .iter()
+            .flat_map(|face| self.face_vertices(*face))
+            .filter(move |neighbour| *neighbour != vertex)
+            .unique()
+    }
+    /// The half-edges leaving `vertex`.
+    pub fn vertex_half_edges(&self, vertex: VertexId) -> impl Iterator<Item = HalfEdgeId> + '_ {
+        self.vertex_faces(vertex).iter().flat_map(move |face| {
+            self.face_half_edges(*face)
+                .into_iter()
+                .filter(move |half_edge| self.half_edge_origin(*half_edge) == vertex)
+        })
+    }
+    pub fn face_half_edges(&self, face: FaceId) -> [HalfEdgeId; 3] {
+        [0, 1, 2].map(|k| (*face * 3 + k).into())
+    }
+    /// The faces sharing an edge with `face`.
+    pub fn face_neighbours(&self, face: FaceId) -> impl Iterator<Item = FaceId> + '_ {
+        self.face_half_edges(face)
+            .into_iter()
+            .filter_map(|half_edge| self.twin(half_edge))
+            .map(|twin| self.half_edge_face(twin))
+    }
+    /// The faces on either side of the edge between `a` and `b`.
+    pub fn edge_faces(&self, a: VertexId, b: VertexId) -> impl Iterator<Item = FaceId> + '_ {
+        [(a, b), (b, a)]
+            .into_iter()
+            .filter_map(|edge| self.half_edges.get(&edge))
+            .map(|half_edge| self.half_edge_face(*half_edge))
+    }
+    /// The half-edge running from `origin` to `target`, if a face has one.
+    pub fn find_half_edge(&self, origin: VertexId, target: VertexId) -> Option<HalfEdgeId> {
+        self.half_edges.get(&(origin, target)).copied()
+    }
+    pub fn half_edge_face(&self, half_edge: HalfEdgeId) -> FaceId {
+        (*half_edge / 3).into()
+    }
+    pub fn half_edge_origin(&self, half_edge: HalfEdgeId) -> VertexId {
+        self.faces[*half_edge as usize / 3][*half_edge as usize % 3]
+    }
+    pub fn half_edge_target(&self, half_edge: HalfEdgeId) -> VertexId {
+        self.half_edge_origin(self.next_half_edge(half_edge))
+    }
+    /// The half-edge after `half_edge` around its face.
+    pub fn next_half_edge(&self, half_edge: HalfEdgeId) -> HalfEdgeId {
+        (*half_edge / 3 * 3 + (*half_edge + 1) % 3).into()
+    }
+    /// The half-edge before `half_edge` around its face.
+    pub fn prev_half_edge(&self, half_edge: HalfEdgeId) -> HalfEdgeId {
+        (*half_edge / 3 * 3 + (*half_edge + 2) % 3).into()
+    }
+    /// The half-edge of the neighbouring face running the other way, `None` on the boundary.
+    pub fn twin(&self, half_edge: HalfEdgeId) -> Option<HalfEdgeId> {
+        self.twins[*half_edge as usize]
+    }
+    pub fn is_boundary(&self, half_edge: HalfEdgeId) -> bool {
+        self.twin(half_edge).is_none()
+    }
+    pub fn boundary_half_edges(&self) -> impl Iterator<Item = HalfEdgeId> + '_ {
+        (0..self.twins.len() as u32)
+            .map(HalfEdgeId::from)
+            .filter(|half_edge| self.is_boundary(*half_edge))
+    }
+    /// The boundary of the mesh as closed loops of half-edges, each following on from the one
+    /// before. A loop that runs into a vertex where it cannot continue ends there.
+    pub fn boundary_loops(&self) -> Vec<Vec<HalfEdgeId>> {
+        let mut visited = vec![false; self.twins.len()];
+        let mut loops = Vec::new();
+        for start in self.boundary_half_edges() {
+            if visited[*start as usize] {
+                continue;
+            }
+            let mut boundary = Vec::new();
+            let mut half_edge = Some(start);
+            while let Some(current) = half_edge.filter(|current| !visited[**current as usize]) {
+                visited[*current as usize] = true;
+                boundary.push(current);
+                half_edge = self
+                    .vertex_half_edges(self.half_edge_target(current))
+                    .find(|next| self.is_boundary(*next) && !visited[**next as usize]);
+            }
+            loops.push(boundary);
+        }
+        loops
     }
     pub fn set_uv<T: Into<[f32; 2]>>(&mut self, vertex: VertexId, uv: T) {
         self.uvs[*vertex as usize] = uv.into();
     }
+    pub fn vertex_uv(&self, vertex: VertexId) -> [f32; 2] {
+        self.uvs[*vertex as usize]
+    }
+    /// Sets the normal of `vertex`, which [`update_normals`](Self::update_normals) overwrites.
+    pub fn set_normal<T: Into<[f32; 3]>>(&mut self, vertex: VertexId, normal: T) {
+        self.normals[*vertex as usize] = normal.into();
+    }
+    pub fn vertex_normal(&self, vertex: VertexId) -> [f32; 3] {
+        self.normals[*vertex as usize]
+    }
+    /// Sets the linear RGBA color of `vertex`. Vertices are white until colored.
+    pub fn set_color<T: Into<[f32; 4]>>(&mut self, vertex: VertexId, color: T) {
+        self.colors[*vertex as usize] = color.into();
+    }
+    pub fn vertex_color(&self, vertex: VertexId) -> [f32; 4] {
+        self.colors[*vertex as usize]
+    }
 
     pub fn bevy_mesh(&self) -> Mesh {
         Mesh::new(PrimitiveTopology::TriangleList)
             .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.vertices.clone())
             .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs.clone())
             .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals.clone())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, self.colors.clone())
             .with_indices(Some(Indices::U32(
                 self.faces
                     .iter()
@@ -136,3 +445,95 @@ impl MeshMap {
             )))
     }
 }
+
+/// The patch `i` belongs to, in a union-find forest of face patches.
+fn find_patch(patches: &mut [usize], i: usize) -> usize {
+    if patches[i] != i {
+        patches[i] = find_patch(patches, patches[i]);
+    }
+    patches[i]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adjacency_of_a_fan() {
+        // Four triangles around a centre vertex, with the last gap left open.
+        let mut mesh = MeshMap::default();
+        let centre = mesh.add_vertex([0.0, 0.0, 0.0]);
+        let rim = (0..5)
+            .map(|i| {
+                let angle = i as f32 * std::f32::consts::FRAC_PI_2;
+                mesh.add_vertex([angle.cos(), angle.sin(), 0.0])
+            })
+            .collect_vec();
+        let faces = (0..4)
+            .map(|i| mesh.add_face([centre, rim[i], rim[i + 1]]))
+            .collect_vec();
+
+        assert_eq!(mesh.vertex_faces(centre), faces.as_slice());
+        assert_eq!(mesh.vertex_neighbours(centre).count(), 5);
+        assert_eq!(
+            mesh.face_neighbours(faces[1]).collect_vec(),
+            [faces[0], faces[2]]
+        );
+        assert_eq!(
+            mesh.edge_faces(centre, rim[2]).collect_vec(),
+            [faces[2], faces[1]]
+        );
+        assert_eq!(mesh.edge_faces(rim[0], rim[2]).count(), 0);
+
+        let half_edge = mesh.find_half_edge(centre, rim[1]).unwrap();
+        let twin = mesh.twin(half_edge).unwrap();
+        assert_eq!(mesh.half_edge_origin(twin), rim[1]);
+        assert_eq!(mesh.half_edge_face(twin), faces[0]);
+        assert_eq!(
+            mesh.prev_half_edge(mesh.next_half_edge(half_edge)),
+            half_edge
+        );
+
+        // The boundary runs around the rim and back through the centre.
+        let loops = mesh.boundary_loops();
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), 6);
+        let boundary = loops[0]
+            .iter()
+            .map(|half_edge| mesh.half_edge_origin(*half_edge))
+            .collect_vec();
+        assert!(boundary.contains(&centre) && rim.iter().all(|v| boundary.contains(v)));
+    }
+
+    #[test]
+    fn test_normals_are_unit_and_split_at_creases() {
+        // Two faces folded at a right angle along the edge between `a` and `b`.
+        let mut mesh = MeshMap::default();
+        let a = mesh.add_vertex([0.0, 0.0, 0.0]);
+        let b = mesh.add_vertex([1.0, 0.0, 0.0]);
+        let c = mesh.add_vertex([0.0, 1.0, 0.0]);
+        let d = mesh.add_vertex([0.0, 0.0, 1.0]);
+        let unused = mesh.add_vertex([5.0, 5.0, 5.0]);
+        mesh.add_face([a, b, c]);
+        mesh.add_face([b, a, d]);
+
+        mesh.update_normals();
+        let normal = Vec3::from(mesh.normals[*a as usize]);
+        assert!((normal.length() - 1.0).abs() < 1e-6);
+        assert!(normal.abs_diff_eq(Vec3::new(0.0, 1.0, 1.0).normalize(), 1e-6));
+        assert_eq!(mesh.normals[*unused as usize], [0.0, 1.0, 0.0]);
+
+        mesh.update_normals_with(&NormalOptions::default().with_crease_angle(0.5));
+        assert_eq!(mesh.vertex_iter().count(), 7);
+        assert!(mesh
+            .find_half_edge(a, b)
+            .is_some_and(|edge| mesh.is_boundary(edge)));
+        for face in mesh.face_iter() {
+            let face_normal = Vec3::from(mesh.compute_face_normal(face)).normalize();
+            for vertex in mesh.face_vertices(face) {
+                let normal = Vec3::from(mesh.normals[*vertex as usize]);
+                assert!(normal.abs_diff_eq(face_normal, 1e-6));
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::{fmt::Debug, str::FromStr};
+
+use bevy::prelude::*;
+
+use crate::{DefectKinds, Prop};
+
+/// Kind of plant organ. Together with an [`OrganId`] it labels the faces of the plant meshes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+pub enum OrganKind {
+    Crown,
+    Petiole,
+    Leaflet,
+    /// The stalk of a truss, including the branches of its cyme.
+    Peduncle,
+    Pedicel,
+    Flower,
+    Fruit,
+    Calyx,
+    Runner,
+    Root,
+    /// A stem of a [`PlantGrammar`](crate::PlantGrammar) plant drawn with `F`, between the
+    /// organs the grammar places.
+    Internode,
+}
+impl OrganKind {
+    pub const ALL: [OrganKind; 11] = [
+        OrganKind::Crown,
+        OrganKind::Petiole,
+        OrganKind::Leaflet,
+        OrganKind::Peduncle,
+        OrganKind::Pedicel,
+        OrganKind::Flower,
+        OrganKind::Fruit,
+        OrganKind::Calyx,
+        OrganKind::Runner,
+        OrganKind::Root,
+        OrganKind::Internode,
+    ];
+}
+/// Parses the kind in the name of an OBJ group or glTF mesh, like `Petiole`, in any case.
+impl FromStr for OrganKind {
+    type Err = String;
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        parse_name(&Self::ALL, name).ok_or_else(|| format!("unknown organ `{name}`"))
+    }
+}
+
+/// The variant of `all` whose `Debug` name is `name`, ignoring case and surrounding spaces.
+pub(crate) fn parse_name<T: Copy + Debug>(all: &[T], name: &str) -> Option<T> {
+    (all.iter().copied()).find(|variant| format!("{variant:?}").eq_ignore_ascii_case(name.trim()))
+}
+
+/// Identifies an organ within its plant. Ids are derived from the organ's place in the plant,
+/// so the same spec and seed give the same ids on every run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+pub struct OrganId(pub u64);
+impl OrganId {
+    /// Hashes the name of the organ, which spells out its place in the plant.
+    pub fn from_name(name: &str) -> Self {
+        Self(fnv1a(0xcbf2_9ce4_8422_2325, name.as_bytes()))
+    }
+    /// The id of an organ of `kind` borne on this one, like the flower at the end of a pedicel.
+    pub fn part(self, kind: OrganKind) -> Self {
+        Self(fnv1a(self.0, &[kind as u8]))
+    }
+}
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The organ a particle belongs to, and the plant that organ belongs to.
+///
+/// The meshes of a plant label every face with its organ, see [`MeshMap::face_label`]. Flowers,
+/// fruits and calyxes are borne by the particle at the end of their pedicel, whose organ is
+/// the pedicel; their own organs follow from it with [`Organ::part`].
+///
+/// [`MeshMap::face_label`]: crate::MeshMap::face_label
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub struct Organ {
+    pub kind: OrganKind,
+    pub id: OrganId,
+    pub plant: Entity,
+    /// Kinds of the defects on the organ, set on the labels of defective fruits.
+    pub defects: DefectKinds,
+}
+impl Organ {
+    pub fn new(kind: OrganKind, name: &str, plant: Entity) -> Self {
+        Self {
+            kind,
+            id: OrganId::from_name(name),
+            plant,
+            defects: DefectKinds::default(),
+        }
+    }
+    pub fn with_defects(mut self, defects: DefectKinds) -> Self {
+        self.defects = defects;
+        self
+    }
+    /// The organ of `kind` borne on this one.
+    pub fn part(&self, kind: OrganKind) -> Self {
+        Self {
+            kind,
+            id: self.id.part(kind),
+            plant: self.plant,
+            defects: DefectKinds::default(),
+        }
+    }
+}
+
+/// What a face of a mesh shows, see [`MeshMap::face_label`].
+///
+/// [`MeshMap::face_label`]: crate::MeshMap::face_label
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum FaceLabel {
+    Organ(Organ),
+    Prop(Prop),
+}
+impl FaceLabel {
+    /// The organ of a face of a plant, `None` for props.
+    pub fn organ(self) -> Option<Organ> {
+        match self {
+            Self::Organ(organ) => Some(organ),
+            Self::Prop(_) => None,
+        }
+    }
+}
+impl From<Organ> for FaceLabel {
+    fn from(organ: Organ) -> Self {
+        Self::Organ(organ)
+    }
+}
+impl From<Prop> for FaceLabel {
+    fn from(prop: Prop) -> Self {
+        Self::Prop(prop)
+    }
+}
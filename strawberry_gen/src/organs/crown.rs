@@ -0,0 +1,114 @@
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use iter_tools::Itertools;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    spawn_leaf, spawn_runners, spawn_stem_chain, spawn_truss, OrganKind, PlantSpec,
+    SizeDistribution, StemChain,
+};
+
+/// Placement of the crowns on the plant base and of the petioles and trusses on each crown.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct RosetteSpec {
+    /// Azimuth between two successive organs on a crown, in radians. The golden angle gives
+    /// the spiral phyllotaxy of strawberry crowns.
+    pub divergence_angle: f32,
+    /// Angle of the petioles away from the vertical, in radians.
+    pub petiole_elevation: SizeDistribution,
+    /// Angle of the trusses away from the vertical, in radians.
+    pub truss_elevation: SizeDistribution,
+    /// Angle of branch crowns away from the vertical when a plant has more than one crown.
+    pub crown_elevation: SizeDistribution,
+}
+impl Default for RosetteSpec {
+    fn default() -> Self {
+        Self {
+            divergence_angle: PI * (3.0 - 5f32.sqrt()),
+            petiole_elevation: SizeDistribution::new(0.6, 0.15),
+            truss_elevation: SizeDistribution::new(0.45, 0.15),
+            crown_elevation: SizeDistribution::new(0.5, 0.1),
+        }
+    }
+}
+
+/// Spawns the crowns of a plant off `base`, each with its petioles and trusses arranged on a
+/// phyllotactic spiral and its runners. `rotation` turns all crowns relative to `base`.
+pub(crate) fn spawn_crowns(
+    commands: &mut Commands,
+    rng: &mut ChaCha8Rng,
+    base: &StemChain,
+    spec: &PlantSpec,
+    rotation: Quat,
+) {
+    let base_rotation = rotation;
+    let rosette = &spec.rosette;
+    let crowns = spec.crown.count.sample_count(rng);
+    let crown_azimuth = rng.gen_range(0.0..TAU);
+    for c in 0..crowns {
+        let rotation = if crowns > 1 {
+            let azimuth = crown_azimuth + c as f32 / crowns as f32 * TAU;
+            base_rotation
+                * Quat::from_rotation_y(azimuth)
+                * Quat::from_rotation_x(rosette.crown_elevation.sample(rng))
+        } else {
+            base_rotation
+        };
+        let name = format!("Crown {c}");
+        let crown = spawn_stem_chain(
+            commands,
+            rng,
+            &name,
+            OrganKind::Crown,
+            base,
+            &spec.crown,
+            rotation,
+        );
+        spawn_rosette(commands, rng, &name, &crown, spec);
+    }
+}
+
+/// Spawns the petioles and trusses of `crown` on a phyllotactic spiral, and its runners.
+pub(crate) fn spawn_rosette(
+    commands: &mut Commands,
+    rng: &mut ChaCha8Rng,
+    name: &str,
+    crown: &StemChain,
+    spec: &PlantSpec,
+) {
+    let rosette = &spec.rosette;
+    let petioles = spec.petiole.count.sample_count(rng);
+    let trusses = spec.truss.count.sample_count(rng);
+    let organs = petioles + trusses;
+    // Trusses grow from the axils between the leaves, spread evenly over the spiral.
+    let truss_positions = (1..=trusses)
+        .map(|t| t * organs / (trusses + 1))
+        .collect_vec();
+    let mut azimuth = rng.gen_range(0.0..TAU);
+    let (mut p, mut t) = (0, 0);
+    for i in 0..organs {
+        azimuth += rosette.divergence_angle;
+        if truss_positions.contains(&i) && t < trusses {
+            let rotation = Quat::from_rotation_y(azimuth)
+                * Quat::from_rotation_x(rosette.truss_elevation.sample(rng));
+            let name = format!("{name} Truss {t}");
+            let truss = spawn_truss(commands, rng, &name, crown, spec, rotation);
+            let growth = &spec.growth;
+            truss.delay_growth(
+                commands,
+                growth.flowering_day + t as f32 * growth.truss_interval,
+            );
+            t += 1;
+        } else {
+            let rotation = Quat::from_rotation_y(azimuth)
+                * Quat::from_rotation_x(rosette.petiole_elevation.sample(rng));
+            let name = format!("{name} Petiole {p}");
+            let leaf = spawn_leaf(commands, rng, &name, crown, spec, rotation);
+            leaf.delay_growth(commands, i as f32 * spec.growth.plastochron);
+            p += 1;
+        }
+    }
+    spawn_runners(commands, rng, name, crown, spec);
+}
@@ -0,0 +1,254 @@
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use bevy::prelude::*;
+use iter_tools::Itertools;
+use rand::Rng;
+
+use crate::{FruitShape, MeshMap, SizeDistribution};
+
+/// Stage of a flower from bud to fruit set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+pub enum FlowerState {
+    /// A bud with its petals folded up over the receptacle.
+    Closed,
+    Open,
+    /// Petals drooping and browning shortly before they drop.
+    Wilting,
+    /// The petals have dropped and the receptacle has set fruit.
+    Fallen,
+}
+impl FlowerState {
+    /// Picks the state for `bloom`, which runs from 0 at anthesis to 1 at fruit set.
+    pub fn from_bloom(bloom: f32) -> Self {
+        match bloom {
+            b if b < 0.0 => Self::Closed,
+            b if b < 0.7 => Self::Open,
+            b if b < 1.0 => Self::Wilting,
+            _ => Self::Fallen,
+        }
+    }
+    /// Angle of the petals above the plane perpendicular to the pedicel, in radians.
+    pub fn petal_angle(&self) -> f32 {
+        match self {
+            Self::Closed => 1.3,
+            Self::Open => 0.15,
+            Self::Wilting | Self::Fallen => -0.5,
+        }
+    }
+    /// Size of the flower relative to its open size.
+    pub fn size_factor(&self) -> f32 {
+        match self {
+            Self::Closed => 0.6,
+            Self::Open => 1.0,
+            Self::Wilting | Self::Fallen => 0.9,
+        }
+    }
+    pub fn petal_color(&self) -> Color {
+        match self {
+            Self::Closed => Color::rgb(0.9, 0.95, 0.8),
+            Self::Open => Color::rgb(0.97, 0.97, 0.93),
+            Self::Wilting | Self::Fallen => Color::rgb(0.8, 0.72, 0.55),
+        }
+    }
+}
+
+/// Shape of an open flower.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct FlowerShape {
+    pub petals: u32,
+    /// Length of a petal from the receptacle to its rim.
+    pub petal_length: f32,
+    /// Widest petal width, relative to the petal length.
+    pub petal_width: f32,
+    /// How deep a petal is cupped, relative to its width.
+    pub cup: f32,
+    pub receptacle_radius: f32,
+    pub stamens: u32,
+    /// Stamen length, relative to the receptacle radius.
+    pub stamen_length: f32,
+}
+
+/// Distributions the [`FlowerShape`] of every flower is drawn from.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct FlowerSpec {
+    pub petals: SizeDistribution,
+    pub petal_length: SizeDistribution,
+    pub petal_width: SizeDistribution,
+    pub cup: SizeDistribution,
+    pub receptacle_radius: SizeDistribution,
+    pub stamens: SizeDistribution,
+    pub stamen_length: SizeDistribution,
+}
+impl Default for FlowerSpec {
+    fn default() -> Self {
+        Self {
+            petals: SizeDistribution::constant(5.0),
+            petal_length: SizeDistribution::new(0.12, 0.02),
+            petal_width: SizeDistribution::new(0.9, 0.1),
+            cup: SizeDistribution::new(0.15, 0.05),
+            receptacle_radius: SizeDistribution::new(0.035, 0.005),
+            stamens: SizeDistribution::new(20.0, 4.0),
+            stamen_length: SizeDistribution::new(0.9, 0.15),
+        }
+    }
+}
+impl FlowerSpec {
+    pub fn sample(&self, rng: &mut impl Rng) -> FlowerShape {
+        FlowerShape {
+            petals: self.petals.sample_count(rng),
+            petal_length: self.petal_length.sample(rng),
+            petal_width: self.petal_width.sample(rng),
+            cup: self.cup.sample(rng),
+            receptacle_radius: self.receptacle_radius.sample(rng),
+            stamens: self.stamens.sample_count(rng),
+            stamen_length: self.stamen_length.sample(rng),
+        }
+    }
+}
+
+/// The flower at a pedicel tip, facing along the particle's local Y axis.
+///
+/// It stays on the tip after fruit set, with its petals fallen, next to the
+/// [`Fruit`](crate::Fruit) grown from `fruit`.
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct Flower {
+    pub shape: FlowerShape,
+    pub state: FlowerState,
+    pub fruit: FruitShape,
+    /// Size relative to the flower's full size, which the bud grows to along with its pedicel.
+    pub growth: f32,
+}
+
+const RECEPTACLE_YELLOW: Color = Color::rgb(0.95, 0.8, 0.15);
+const FILAMENT_YELLOW: Color = Color::rgb(0.9, 0.88, 0.5);
+const ANTHER_YELLOW: Color = Color::rgb(0.95, 0.7, 0.1);
+
+impl Flower {
+    /// Appends the petals, receptacle and stamens to `mesh`, placed by `transform`. A flower
+    /// that has set fruit adds nothing.
+    pub fn add_mesh(&self, mesh: &mut MeshMap, transform: &Transform) {
+        if self.state == FlowerState::Fallen {
+            return;
+        }
+        let transform = transform.with_scale(Vec3::splat(self.state.size_factor() * self.growth));
+        self.add_petals(mesh, &transform);
+        self.add_receptacle(mesh, &transform);
+        if self.state != FlowerState::Closed {
+            self.add_stamens(mesh, &transform);
+        }
+    }
+
+    /// Adds every petal as a cupped, rounded blade spreading from the rim of the receptacle.
+    fn add_petals(&self, mesh: &mut MeshMap, transform: &Transform) {
+        let shape = &self.shape;
+        let tilt = self.state.petal_angle();
+        let color = self.state.petal_color().as_linear_rgba_f32();
+        let (rows, columns) = (6, 4);
+        for k in 0..shape.petals {
+            let angle = k as f32 / shape.petals as f32 * TAU;
+            let radial = Vec3::new(angle.cos(), 0.0, angle.sin());
+            let side = Vec3::Y.cross(radial);
+            let direction = radial * tilt.cos() + Vec3::Y * tilt.sin();
+            let normal = direction.cross(side);
+            let start = radial * shape.receptacle_radius * 0.8;
+            let grid = (0..=rows)
+                .map(|row| {
+                    let s = row as f32 / rows as f32;
+                    // Obovate outline: narrow claw at the base, round at the rim.
+                    let half_width = 0.5
+                        * shape.petal_width
+                        * shape.petal_length
+                        * (PI * (0.1 + 0.8 * s.sqrt())).sin();
+                    (0..=columns)
+                        .map(|column| {
+                            let u = 2.0 * column as f32 / columns as f32 - 1.0;
+                            let cup = shape.cup * half_width * (1.0 - u * u);
+                            let local = start
+                                + direction * s * shape.petal_length
+                                + side * u * half_width
+                                + normal * cup;
+                            let vertex = mesh.add_vertex(transform.transform_point(local));
+                            mesh.set_uv(vertex, [(u + 1.0) * 0.5, s]);
+                            mesh.set_color(vertex, color);
+                            vertex
+                        })
+                        .collect_vec()
+                })
+                .collect_vec();
+            for (a, b) in grid.iter().tuple_windows() {
+                for i in 0..columns {
+                    mesh.add_face((a[i], a[i + 1], b[i]));
+                    mesh.add_face((b[i], a[i + 1], b[i + 1]));
+                }
+            }
+        }
+    }
+
+    /// Adds the receptacle as a dome on top of the pedicel.
+    fn add_receptacle(&self, mesh: &mut MeshMap, transform: &Transform) {
+        let radius = self.shape.receptacle_radius;
+        let color = RECEPTACLE_YELLOW.as_linear_rgba_f32();
+        let (rows, columns) = (4, 10);
+        let grid = (0..=rows)
+            .map(|row| {
+                let polar = row as f32 / rows as f32 * FRAC_PI_2;
+                (0..=columns)
+                    .map(|column| {
+                        let azimuth = column as f32 / columns as f32 * TAU;
+                        let local = Vec3::new(
+                            radius * polar.sin() * azimuth.cos(),
+                            0.8 * radius * polar.cos(),
+                            radius * polar.sin() * azimuth.sin(),
+                        );
+                        let vertex = mesh.add_vertex(transform.transform_point(local));
+                        mesh.set_uv(vertex, [column as f32 / columns as f32, polar / FRAC_PI_2]);
+                        mesh.set_color(vertex, color);
+                        vertex
+                    })
+                    .collect_vec()
+            })
+            .collect_vec();
+        for (a, b) in grid.iter().tuple_windows() {
+            for i in 0..columns {
+                mesh.add_face((a[i], a[i + 1], b[i]));
+                mesh.add_face((b[i], a[i + 1], b[i + 1]));
+            }
+        }
+    }
+
+    /// Adds a ring of stamens around the receptacle, each a thin filament carrying an anther.
+    fn add_stamens(&self, mesh: &mut MeshMap, transform: &Transform) {
+        let shape = &self.shape;
+        let length = shape.stamen_length * shape.receptacle_radius;
+        let width = 0.08 * length;
+        let filament_color = FILAMENT_YELLOW.as_linear_rgba_f32();
+        let anther_color = ANTHER_YELLOW.as_linear_rgba_f32();
+        for k in 0..shape.stamens {
+            let angle = k as f32 / shape.stamens as f32 * TAU;
+            let radial = Vec3::new(angle.cos(), 0.0, angle.sin());
+            let side = Vec3::Y.cross(radial);
+            let base = radial * shape.receptacle_radius * 1.05;
+            let tip = base + (radial * 0.4 + Vec3::Y).normalize() * length;
+
+            let [a, b, c] = [base - side * width, base + side * width, tip].map(|local| {
+                let vertex = mesh.add_vertex(transform.transform_point(local));
+                mesh.set_color(vertex, filament_color);
+                vertex
+            });
+            mesh.add_face((a, b, c));
+
+            let corners = [0.0, TAU / 3.0, 2.0 * TAU / 3.0].map(|corner: f32| {
+                tip + (radial * corner.cos() + side * corner.sin()) * width * 1.5
+            });
+            let apex = tip + Vec3::Y * width * 2.0;
+            let [a, b, c, apex] = [corners[0], corners[1], corners[2], apex].map(|local| {
+                let vertex = mesh.add_vertex(transform.transform_point(local));
+                mesh.set_color(vertex, anther_color);
+                vertex
+            });
+            mesh.add_face((a, b, apex));
+            mesh.add_face((b, c, apex));
+            mesh.add_face((c, a, apex));
+        }
+    }
+}
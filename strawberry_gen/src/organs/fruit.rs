@@ -0,0 +1,378 @@
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use iter_tools::Itertools;
+use rand::Rng;
+
+use crate::{
+    DefectKind, DefectKinds, FaceLabel, FruitDefect, FruitDefectSpec, MeshMap, OrganKind,
+    SizeDistribution,
+};
+
+/// Developmental stage of a fruit, from fruit set to past harvest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+pub enum Ripeness {
+    Green,
+    White,
+    Turning,
+    Red,
+    Overripe,
+}
+impl Ripeness {
+    /// Picks the stage for a `maturity` between 0 (just set) and 1 (overripe).
+    pub fn from_maturity(maturity: f32) -> Self {
+        match maturity {
+            m if m < 0.25 => Self::Green,
+            m if m < 0.45 => Self::White,
+            m if m < 0.65 => Self::Turning,
+            m if m < 0.9 => Self::Red,
+            _ => Self::Overripe,
+        }
+    }
+    /// Skin color at the calyx and at the tip. Fruits color up from the tip.
+    pub fn skin_colors(&self) -> (Color, Color) {
+        match self {
+            Self::Green => (Color::rgb(0.45, 0.65, 0.25), Color::rgb(0.55, 0.72, 0.3)),
+            Self::White => (Color::rgb(0.85, 0.9, 0.7), Color::rgb(0.95, 0.93, 0.85)),
+            Self::Turning => (Color::rgb(0.95, 0.9, 0.75), Color::rgb(0.9, 0.25, 0.2)),
+            Self::Red => (Color::rgb(0.8, 0.08, 0.08), Color::rgb(0.85, 0.1, 0.08)),
+            Self::Overripe => (Color::rgb(0.45, 0.03, 0.06), Color::rgb(0.5, 0.04, 0.06)),
+        }
+    }
+    pub fn achene_color(&self) -> Color {
+        match self {
+            Self::Green => Color::rgb(0.6, 0.7, 0.3),
+            Self::White => Color::rgb(0.85, 0.8, 0.4),
+            Self::Turning | Self::Red => Color::rgb(0.9, 0.75, 0.25),
+            Self::Overripe => Color::rgb(0.5, 0.35, 0.15),
+        }
+    }
+    /// Size of the fruit relative to its fully grown size.
+    pub fn size_factor(&self) -> f32 {
+        match self {
+            Self::Green => 0.55,
+            Self::White => 0.8,
+            Self::Turning => 0.92,
+            Self::Red => 1.0,
+            Self::Overripe => 0.95,
+        }
+    }
+    /// How far the achenes stand out of the skin, relative to their size. They sink in as the
+    /// receptacle swells.
+    pub fn achene_protrusion(&self) -> f32 {
+        match self {
+            Self::Green => 0.8,
+            Self::White => 0.5,
+            Self::Turning => 0.3,
+            Self::Red => 0.1,
+            Self::Overripe => 0.0,
+        }
+    }
+    /// Angle of the sepals away from the fruit body; they clasp young fruits and curl back on
+    /// ripe ones.
+    pub fn sepal_angle(&self) -> f32 {
+        match self {
+            Self::Green => 0.3,
+            Self::White => 0.1,
+            Self::Turning => -0.1,
+            Self::Red | Self::Overripe => -0.4,
+        }
+    }
+}
+
+/// Shape of a fully grown fruit.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct FruitShape {
+    /// Length from the calyx to the tip.
+    pub length: f32,
+    /// Widest diameter, relative to the length.
+    pub width: f32,
+    /// Where the fruit is widest, from 0 at the calyx to 1 at the tip.
+    pub shoulder: f32,
+    /// Around 1 the fruit is a rounded heart, higher values narrow it into a cone.
+    pub taper: f32,
+    pub achenes: u32,
+    pub sepals: u32,
+    /// Sepal length, relative to the fruit width.
+    pub sepal_length: f32,
+    /// How much darker than the reference colors of its ripeness the skin is, from 0 to 1.
+    pub darkness: f32,
+    /// Turn of the skin hue away from the reference colors of its ripeness, in degrees, from
+    /// crimson below 0 to orange above.
+    pub hue: f32,
+    /// Deformities and blemishes, which label the fruit as defective.
+    pub defects: Vec<FruitDefect>,
+}
+
+/// Distributions the [`FruitShape`] and ripeness of every fruit are drawn from.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct FruitSpec {
+    pub length: SizeDistribution,
+    pub width: SizeDistribution,
+    pub shoulder: SizeDistribution,
+    pub taper: SizeDistribution,
+    pub achenes: SizeDistribution,
+    pub sepals: SizeDistribution,
+    pub sepal_length: SizeDistribution,
+    pub darkness: SizeDistribution,
+    pub hue: SizeDistribution,
+    pub defects: FruitDefectSpec,
+    /// Maturity between 0 and 1, see [`Ripeness::from_maturity`].
+    pub maturity: SizeDistribution,
+}
+impl Default for FruitSpec {
+    fn default() -> Self {
+        Self {
+            length: SizeDistribution::new(0.35, 0.08),
+            width: SizeDistribution::new(0.85, 0.1),
+            shoulder: SizeDistribution::new(0.3, 0.05),
+            taper: SizeDistribution::new(1.4, 0.3),
+            achenes: SizeDistribution::new(180.0, 40.0),
+            sepals: SizeDistribution::new(10.0, 1.0),
+            sepal_length: SizeDistribution::new(0.45, 0.1),
+            darkness: SizeDistribution::new(0.1, 0.1),
+            hue: SizeDistribution::constant(0.0),
+            defects: FruitDefectSpec::default(),
+            maturity: SizeDistribution::new(0.5, 0.5),
+        }
+    }
+}
+impl FruitSpec {
+    pub fn sample(&self, rng: &mut impl Rng) -> Fruit {
+        let maturity = self.maturity.sample(rng);
+        self.sample_with_maturity(rng, maturity)
+    }
+    /// Draws a fruit shape for a fruit of the given `maturity`.
+    pub fn sample_with_maturity(&self, rng: &mut impl Rng, maturity: f32) -> Fruit {
+        let shape = FruitShape {
+            length: self.length.sample(rng),
+            width: self.width.sample(rng),
+            shoulder: self.shoulder.sample(rng).clamp(0.05, 0.95),
+            taper: self.taper.sample(rng),
+            achenes: self.achenes.sample_count(rng),
+            sepals: self.sepals.sample_count(rng),
+            sepal_length: self.sepal_length.sample(rng),
+            darkness: self.darkness.sample(rng).clamp(0.0, 1.0),
+            hue: self.hue.sample(rng),
+            defects: self.defects.sample(rng),
+        };
+        Fruit {
+            shape,
+            ripeness: Ripeness::from_maturity(maturity.clamp(0.0, 1.0)),
+        }
+    }
+}
+
+/// A fruit hanging from the particle it is attached to, growing along the particle's local Y
+/// axis with the calyx at the particle.
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct Fruit {
+    pub shape: FruitShape,
+    pub ripeness: Ripeness,
+}
+
+const CALYX_GREEN: Color = Color::rgb(0.2, 0.45, 0.12);
+
+impl Fruit {
+    /// Length of the fruit at its current ripeness.
+    pub fn length(&self) -> f32 {
+        self.shape.length * self.ripeness.size_factor()
+    }
+
+    /// Volume of the fruit at its current ripeness, taken as an ellipsoid.
+    pub fn volume(&self) -> f32 {
+        let length = self.length();
+        PI / 6.0 * length * (self.shape.width * length).powi(2)
+    }
+
+    /// Radius of the fruit body at `t` in `[0, 1]` from the calyx to the tip.
+    pub fn radius(&self, t: f32) -> f32 {
+        let shape = &self.shape;
+        let exponent = 0.5f32.ln() / shape.shoulder.ln();
+        let profile = (PI * t.clamp(0.0, 1.0).powf(exponent)).sin().max(0.0);
+        0.5 * shape.width * self.length() * profile.powf(0.5 * shape.taper)
+    }
+
+    /// Kinds of the defects on this fruit, for labelling it.
+    pub fn defect_kinds(&self) -> DefectKinds {
+        self.shape
+            .defects
+            .iter()
+            .map(|defect| defect.kind)
+            .collect()
+    }
+
+    /// The defects that reach the surface at `t` and `angle`, with their weights and the
+    /// surface point relative to them.
+    fn defects_at(&self, t: f32, angle: f32) -> impl Iterator<Item = (&FruitDefect, f32, Vec2)> {
+        let width = self.shape.width;
+        self.shape
+            .defects
+            .iter()
+            .map(move |defect| {
+                let weight = defect.weight(t, angle, width);
+                (defect, weight, defect.local_point(t, angle, width))
+            })
+            .filter(|(_, weight, _)| *weight > 0.0)
+    }
+
+    /// Point on the body surface in the fruit's local frame.
+    pub(crate) fn surface_point(&self, t: f32, angle: f32) -> Vec3 {
+        let radius = self
+            .defects_at(t, angle)
+            .fold(self.radius(t), |radius, (defect, weight, _)| {
+                radius * defect.shrink(weight, angle)
+            });
+        Vec3::new(
+            radius * angle.cos(),
+            t * self.length(),
+            radius * angle.sin(),
+        )
+    }
+
+    fn skin_color(&self, t: f32, angle: f32) -> [f32; 4] {
+        let (calyx, tip) = self.ripeness.skin_colors();
+        let blend = t * t * (3.0 - 2.0 * t);
+        let turn = |color: Color| {
+            let hue = (color.h() + self.shape.hue).rem_euclid(360.0);
+            Vec4::from(color.with_h(hue).as_linear_rgba_f32())
+        };
+        let (calyx, tip) = (turn(calyx), turn(tip));
+        let color = calyx.lerp(tip, blend);
+        let color = (color.truncate() * (1.0 - self.shape.darkness)).extend(color.w);
+        self.defects_at(t, angle)
+            .fold(color, |color, (defect, weight, point)| {
+                defect.tint(color, weight, point)
+            })
+            .into()
+    }
+
+    /// Appends the fruit body, its achenes and the calyx to `mesh`, placed by `transform`. The
+    /// body and achenes are labelled with the fruit's organ and the kinds of its defects, the
+    /// calyx faces with the calyx borne on the fruit's organ.
+    pub fn add_mesh(&self, mesh: &mut MeshMap, transform: &Transform) {
+        let label = mesh.label();
+        let fruit = label.and_then(FaceLabel::organ);
+        mesh.set_label(fruit.map(|fruit| fruit.with_defects(self.defect_kinds()).into()));
+        self.add_body(mesh, transform);
+        self.add_achenes(mesh, transform);
+        mesh.set_label(fruit.map(|fruit| fruit.part(OrganKind::Calyx).into()));
+        self.add_calyx(mesh, transform);
+        mesh.set_label(label);
+    }
+
+    fn add_body(&self, mesh: &mut MeshMap, transform: &Transform) {
+        // Defects need a finer grid to show their outline.
+        let (rows, columns) = match self.shape.defects.is_empty() {
+            true => (16, 16),
+            false => (32, 48),
+        };
+        let grid = (0..=rows)
+            .map(|row| {
+                let t = row as f32 / rows as f32;
+                (0..=columns)
+                    .map(|column| {
+                        let u = column as f32 / columns as f32;
+                        let local = self.surface_point(t, u * TAU);
+                        let vertex = mesh.add_vertex(transform.transform_point(local));
+                        mesh.set_uv(vertex, [u, t]);
+                        mesh.set_color(vertex, self.skin_color(t, u * TAU));
+                        vertex
+                    })
+                    .collect_vec()
+            })
+            .collect_vec();
+        for (a, b) in grid.iter().tuple_windows() {
+            for i in 0..columns {
+                mesh.add_face((a[i], b[i], a[i + 1]));
+                mesh.add_face((a[i + 1], b[i], b[i + 1]));
+            }
+        }
+    }
+
+    /// Places the achenes on a golden angle spiral over the body, each as a small pyramid
+    /// pointing out of the skin. Defects raise, tint or remove the achenes they cover.
+    fn add_achenes(&self, mesh: &mut MeshMap, transform: &Transform) {
+        let golden_angle = PI * (3.0 - 5f32.sqrt());
+        let size = 0.04 * self.shape.width * self.length();
+        let protrusion = self.ripeness.achene_protrusion();
+        let color = self.ripeness.achene_color().as_linear_rgba_f32();
+        for k in 0..self.shape.achenes {
+            let t = 0.12 + 0.83 * (k as f32 + 0.5) / self.shape.achenes as f32;
+            let angle = k as f32 * golden_angle;
+            let defects = self.defects_at(t, angle).collect_vec();
+            if defects
+                .iter()
+                .any(|(defect, weight, _)| defect.removes_achenes(*weight))
+            {
+                continue;
+            }
+            let protrusion = protrusion
+                + defects
+                    .iter()
+                    .map(|(defect, weight, _)| defect.achene_protrusion(*weight))
+                    .sum::<f32>();
+            let color: [f32; 4] = defects
+                .iter()
+                .filter(|(defect, _, _)| defect.kind == DefectKind::GrayMold)
+                .fold(Vec4::from(color), |color, (defect, weight, point)| {
+                    defect.tint(color, *weight, *point)
+                })
+                .into();
+            let center = self.surface_point(t, angle);
+            let along = self.surface_point(t + 0.01, angle) - center;
+            let around = self.surface_point(t, angle + 0.01) - center;
+            let normal = around.cross(along).normalize_or_zero();
+            let along = along.normalize_or_zero();
+            let around = normal.cross(along);
+
+            let base = center - normal * size * 0.5;
+            let corners = [0.0, TAU / 3.0, 2.0 * TAU / 3.0].map(|corner: f32| {
+                base + (along * corner.cos() + around * corner.sin()) * size * 0.5
+            });
+            let apex = center + normal * size * protrusion;
+            let [a, b, c, apex] = [corners[0], corners[1], corners[2], apex].map(|local| {
+                let vertex = mesh.add_vertex(transform.transform_point(local));
+                mesh.set_color(vertex, color);
+                vertex
+            });
+            mesh.add_face((a, b, apex));
+            mesh.add_face((b, c, apex));
+            mesh.add_face((c, a, apex));
+        }
+    }
+
+    /// Spreads the sepals radially from the top of the fruit as tapered strips.
+    fn add_calyx(&self, mesh: &mut MeshMap, transform: &Transform) {
+        let length = self.shape.sepal_length * self.shape.width * self.length();
+        let tilt = self.ripeness.sepal_angle();
+        let color = CALYX_GREEN.as_linear_rgba_f32();
+        let segments = 4;
+        for k in 0..self.shape.sepals {
+            let angle = k as f32 / self.shape.sepals as f32 * TAU;
+            let radial = Vec3::new(angle.cos(), 0.0, angle.sin());
+            let side = Vec3::Y.cross(radial);
+            let direction = radial * tilt.cos() + Vec3::Y * tilt.sin();
+            let start = self.surface_point(0.03, angle);
+            let rows = (0..=segments)
+                .map(|i| {
+                    let s = i as f32 / segments as f32;
+                    let center = start + direction * s * length;
+                    let half_width = 0.18 * length * (PI * (0.15 + 0.85 * s)).sin();
+                    [-1.0, 1.0].map(|sign| {
+                        let vertex = mesh.add_vertex(
+                            transform.transform_point(center + side * sign * half_width),
+                        );
+                        mesh.set_uv(vertex, [(sign + 1.0) * 0.5, s]);
+                        mesh.set_color(vertex, color);
+                        vertex
+                    })
+                })
+                .collect_vec();
+            for (a, b) in rows.iter().tuple_windows() {
+                mesh.add_face((a[0], a[1], b[0]));
+                mesh.add_face((b[0], a[1], b[1]));
+            }
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    f32::consts::{PI, TAU},
+    str::FromStr,
+};
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{parse_name, value_noise, SizeDistribution};
+
+/// Kind of a deformity or blemish on a fruit, as used to label defective fruits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+pub enum DefectKind {
+    /// Creased, lumpy flank where poorly pollinated achenes left the receptacle undeveloped.
+    CatFacing,
+    /// Shrunken pale tip studded with protruding achenes.
+    SeedyTip,
+    /// Cavity pecked into the flesh by a bird, with a dried rim.
+    BirdPeck,
+    /// Soft grey patch of *Botrytis* mould that also covers the achenes.
+    GrayMold,
+    /// Dark, slightly sunken patch of crushed flesh.
+    Bruise,
+    /// Bleached patch on the side facing the sun.
+    Sunburn,
+}
+impl DefectKind {
+    pub const ALL: [DefectKind; 6] = [
+        DefectKind::CatFacing,
+        DefectKind::SeedyTip,
+        DefectKind::BirdPeck,
+        DefectKind::GrayMold,
+        DefectKind::Bruise,
+        DefectKind::Sunburn,
+    ];
+
+    /// Bit of the kind in a [`DefectKinds`], following the order of [`DefectKind::ALL`].
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+
+    /// Depth to which the defect at full severity sinks the body, relative to its radius.
+    fn indent(&self) -> f32 {
+        match self {
+            Self::CatFacing => 0.3,
+            Self::SeedyTip => 0.45,
+            Self::BirdPeck => 0.5,
+            Self::GrayMold => 0.1,
+            Self::Bruise => 0.06,
+            Self::Sunburn => 0.0,
+        }
+    }
+}
+/// Parses a defect listed after the organ in an OBJ group or in glTF extras, like `BirdPeck`,
+/// in any case.
+impl FromStr for DefectKind {
+    type Err = String;
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        parse_name(&Self::ALL, name).ok_or_else(|| format!("unknown defect `{name}`"))
+    }
+}
+
+/// A set of [`DefectKind`]s, like the kinds of the defects on a fruit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Reflect)]
+pub struct DefectKinds(u32);
+impl DefectKinds {
+    /// Bits of the kinds in the set, one for every kind in the order of [`DefectKind::ALL`].
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+    /// The set of the kinds whose bits are set, ignoring bits past the last kind.
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits & ((1 << DefectKind::ALL.len()) - 1))
+    }
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+    pub fn contains(self, kind: DefectKind) -> bool {
+        self.0 & kind.bit() != 0
+    }
+    /// The kinds in the set, in the order of [`DefectKind::ALL`].
+    pub fn iter(self) -> impl Iterator<Item = DefectKind> {
+        DefectKind::ALL
+            .into_iter()
+            .filter(move |kind| self.contains(*kind))
+    }
+}
+impl FromIterator<DefectKind> for DefectKinds {
+    fn from_iter<I: IntoIterator<Item = DefectKind>>(kinds: I) -> Self {
+        Self(kinds.into_iter().fold(0, |bits, kind| bits | kind.bit()))
+    }
+}
+
+const UNDEVELOPED_WHITE: Color = Color::rgb(0.85, 0.85, 0.6);
+const SEEDY_GREEN: Color = Color::rgb(0.75, 0.82, 0.5);
+const PECKED_FLESH: Color = Color::rgb(0.95, 0.6, 0.5);
+const PECKED_RIM: Color = Color::rgb(0.35, 0.12, 0.06);
+const MOLD_GREY: Color = Color::rgb(0.55, 0.53, 0.5);
+const BRUISE_PURPLE: Color = Color::rgb(0.25, 0.04, 0.1);
+const SUNBURN_BLEACH: Color = Color::rgb(0.9, 0.8, 0.7);
+
+/// A defect centred on a point of the fruit surface.
+///
+/// The surface is addressed like the fruit body: `t` runs from 0 at the calyx to 1 at the tip
+/// and `angle` goes around the fruit axis.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct FruitDefect {
+    pub kind: DefectKind,
+    pub t: f32,
+    pub angle: f32,
+    /// Extent over the surface, relative to the fruit length. A seedy tip reaches this far
+    /// from the tip all around the fruit.
+    pub radius: f32,
+    /// How pronounced the defect is, from 0 to 1.
+    pub severity: f32,
+    /// Seed of the noise that makes the outline irregular.
+    pub seed: u32,
+}
+
+impl FruitDefect {
+    /// Position of the surface point at `t` and `angle` on a fruit of relative `width`, relative
+    /// to the defect and in units of the fruit length. It is continuous all over the defect, so
+    /// it can seed the noise of its outline and texture.
+    pub fn local_point(&self, t: f32, angle: f32, width: f32) -> Vec2 {
+        match self.kind {
+            DefectKind::SeedyTip => Vec2::from_angle(angle) * (1.0 - t + 0.5 * width),
+            _ => {
+                let around = (angle - self.angle + PI).rem_euclid(TAU) - PI;
+                Vec2::new(t - self.t, around * 0.5 * width)
+            }
+        }
+    }
+
+    /// Weight of the defect at `t` and `angle` on a fruit of relative `width`: 1 at its centre,
+    /// falling off to 0 at its edge.
+    pub fn weight(&self, t: f32, angle: f32, width: f32) -> f32 {
+        let distance = match self.kind {
+            DefectKind::SeedyTip => (1.0 - t) / self.radius,
+            _ => self.local_point(t, angle, width).length() / self.radius,
+        };
+        let ragged = match self.kind {
+            DefectKind::SeedyTip => Vec2::from_angle(angle) * 2.0,
+            _ => self.local_point(t, angle, width) * 25.0,
+        };
+        let ragged = 0.8 + 0.4 * self.noise(ragged);
+        let weight = (1.0 - distance * ragged).clamp(0.0, 1.0);
+        weight * weight * (3.0 - 2.0 * weight)
+    }
+
+    /// Factor on the body radius at a point with the given `weight`.
+    pub fn shrink(&self, weight: f32, angle: f32) -> f32 {
+        let depth = match self.kind {
+            // A steep walled cavity rather than a smooth dent.
+            DefectKind::BirdPeck => weight.sqrt(),
+            // Creases running along the fruit.
+            DefectKind::CatFacing => weight * (0.6 + 0.4 * (angle * 9.0).sin()),
+            _ => weight,
+        };
+        1.0 - self.kind.indent() * self.severity * depth
+    }
+
+    /// Blends the skin `color` towards the look of the defect, where `point` is the
+    /// [`local_point`](Self::local_point) of the skin.
+    pub fn tint(&self, color: Vec4, weight: f32, point: Vec2) -> Vec4 {
+        let blend = |color: Vec4, target: Color, amount: f32| {
+            color.lerp(
+                Vec4::from(target.as_linear_rgba_f32()),
+                amount.clamp(0.0, 1.0),
+            )
+        };
+        let amount = self.severity * weight;
+        match self.kind {
+            DefectKind::CatFacing => blend(color, UNDEVELOPED_WHITE, amount),
+            DefectKind::SeedyTip => blend(color, SEEDY_GREEN, 1.5 * amount),
+            DefectKind::BirdPeck => {
+                let color = blend(color, PECKED_RIM, 4.0 * amount);
+                blend(color, PECKED_FLESH, (weight - 0.6) * 5.0 * self.severity)
+            }
+            DefectKind::GrayMold => {
+                let fuzz = 0.6 + 0.8 * self.noise(point * 40.0);
+                blend(color, MOLD_GREY, 2.0 * amount * fuzz)
+            }
+            DefectKind::Bruise => blend(color, BRUISE_PURPLE, 0.8 * amount),
+            DefectKind::Sunburn => blend(color, SUNBURN_BLEACH, amount),
+        }
+    }
+
+    /// Extra protrusion of the achenes at a point with the given `weight`, relative to their
+    /// size. Achenes stand out of undeveloped tissue.
+    pub fn achene_protrusion(&self, weight: f32) -> f32 {
+        match self.kind {
+            DefectKind::CatFacing | DefectKind::SeedyTip => self.severity * weight,
+            _ => 0.0,
+        }
+    }
+
+    /// Whether achenes at a point with the given `weight` have been eaten away.
+    pub fn removes_achenes(&self, weight: f32) -> bool {
+        self.kind == DefectKind::BirdPeck && weight > 0.5
+    }
+
+    fn noise(&self, point: Vec2) -> f32 {
+        value_noise(point, self.seed)
+    }
+}
+
+/// How often fruits show each kind of defect. All probabilities are per fruit.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct FruitDefectSpec {
+    pub cat_facing: f32,
+    pub seedy_tip: f32,
+    pub bird_peck: f32,
+    pub gray_mold: f32,
+    pub bruise: f32,
+    pub sunburn: f32,
+    /// Extent of a defect, relative to the fruit length.
+    pub radius: SizeDistribution,
+    pub severity: SizeDistribution,
+}
+impl Default for FruitDefectSpec {
+    fn default() -> Self {
+        Self {
+            cat_facing: 0.0,
+            seedy_tip: 0.0,
+            bird_peck: 0.0,
+            gray_mold: 0.0,
+            bruise: 0.0,
+            sunburn: 0.0,
+            radius: SizeDistribution::new(0.2, 0.08),
+            severity: SizeDistribution::new(0.6, 0.3),
+        }
+    }
+}
+impl FruitDefectSpec {
+    pub fn probability(&self, kind: DefectKind) -> f32 {
+        match kind {
+            DefectKind::CatFacing => self.cat_facing,
+            DefectKind::SeedyTip => self.seedy_tip,
+            DefectKind::BirdPeck => self.bird_peck,
+            DefectKind::GrayMold => self.gray_mold,
+            DefectKind::Bruise => self.bruise,
+            DefectKind::Sunburn => self.sunburn,
+        }
+    }
+
+    /// Draws the defects of a single fruit.
+    pub fn sample(&self, rng: &mut impl Rng) -> Vec<FruitDefect> {
+        let mut defects = Vec::new();
+        for kind in DefectKind::ALL {
+            if !rng.gen_bool(self.probability(kind).clamp(0.0, 1.0) as f64) {
+                continue;
+            }
+            let radius = self.radius.sample(rng).max(0.02);
+            let (t, radius) = match kind {
+                DefectKind::CatFacing => (rng.gen_range(0.4..0.9), 1.5 * radius),
+                DefectKind::SeedyTip => (1.0, radius + 0.1),
+                DefectKind::BirdPeck => (rng.gen_range(0.5..0.95), 0.5 * radius),
+                _ => (rng.gen_range(0.2..0.95), radius),
+            };
+            defects.push(FruitDefect {
+                kind,
+                t,
+                angle: rng.gen_range(0.0..TAU),
+                radius,
+                severity: self.severity.sample(rng).clamp(0.0, 1.0),
+                seed: rng.gen(),
+            });
+        }
+        defects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::{ExportOptions, FruitSpec, MeshMap, Organ, OrganKind};
+
+    #[test]
+    fn test_bird_peck_dents_and_labels_fruit() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut fruit = FruitSpec::default().sample_with_maturity(&mut rng, 0.7);
+        let intact = fruit.surface_point(0.7, 1.0).length();
+        fruit.shape.defects.push(FruitDefect {
+            kind: DefectKind::BirdPeck,
+            t: 0.7,
+            angle: 1.0,
+            radius: 0.1,
+            severity: 1.0,
+            seed: 0,
+        });
+        assert!(fruit.surface_point(0.7, 1.0).length() < intact);
+        let kinds = fruit.defect_kinds();
+        assert_eq!(kinds.iter().collect::<Vec<_>>(), [DefectKind::BirdPeck]);
+
+        let mut mesh = MeshMap::default();
+        let label = Organ::new(OrganKind::Fruit, "Fruit", Entity::PLACEHOLDER);
+        mesh.set_label(Some(label.into()));
+        fruit.add_mesh(&mut mesh, &Transform::IDENTITY);
+        assert!(mesh.face_iter().count() > 0);
+
+        // The defect kinds are written with the fruit's label.
+        let options = ExportOptions::default();
+        let mut obj = Vec::new();
+        mesh.write_obj(&mut obj, &options).unwrap();
+        let mut ply = Vec::new();
+        mesh.write_ply(&mut ply, &options).unwrap();
+        for read in [
+            MeshMap::read_obj(obj.as_slice()).unwrap(),
+            MeshMap::read_ply(ply.as_slice()).unwrap(),
+        ] {
+            let labels = read
+                .face_iter()
+                .filter_map(|face| read.face_label(face)?.organ());
+            let (fruits, calyxes): (Vec<_>, Vec<_>) =
+                labels.partition(|organ| organ.kind == OrganKind::Fruit);
+            assert!(!fruits.is_empty() && !calyxes.is_empty());
+            assert!(fruits.iter().all(|organ| organ.defects == kinds));
+            assert!(calyxes.iter().all(|organ| organ.defects.is_empty()));
+        }
+    }
+
+    #[test]
+    fn test_bird_peck_tint_follows_severity() {
+        let red = Vec4::new(0.6, 0.02, 0.02, 1.0);
+        let peck = |severity| FruitDefect {
+            kind: DefectKind::BirdPeck,
+            t: 0.5,
+            angle: 0.0,
+            radius: 0.1,
+            severity,
+            seed: 0,
+        };
+        let tint = |severity| peck(severity).tint(red, 0.2, Vec2::ZERO);
+        assert_eq!(tint(0.0), red);
+        assert!(tint(0.5).distance(red) < tint(1.0).distance(red));
+    }
+}
@@ -0,0 +1,284 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use iter_tools::Itertools;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    spawn_stem_chain, LeafCondition, MeshMap, OrganKind, PlantSpec, SizeDistribution, StemChain,
+};
+
+/// The silhouette of a leaflet blade, named after where it is widest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum LeafletOutline {
+    /// Widest below the middle.
+    Ovate,
+    /// Widest at the middle.
+    Elliptic,
+    /// Widest above the middle.
+    Obovate,
+}
+impl LeafletOutline {
+    /// Relative half width of the blade at `t` in `[0, 1]` from base to tip, peaking at 1.
+    pub fn profile(&self, t: f32) -> f32 {
+        let widest: f32 = match self {
+            Self::Ovate => 0.4,
+            Self::Elliptic => 0.5,
+            Self::Obovate => 0.6,
+        };
+        let exponent = 0.5f32.ln() / widest.ln();
+        (PI * t.clamp(0.0, 1.0).powf(exponent)).sin().max(0.0)
+    }
+}
+
+/// Parametric shape of a single leaflet blade. Sizes are relative to the midrib length.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct LeafletShape {
+    pub outline: LeafletOutline,
+    /// Widest blade width.
+    pub width: f32,
+    /// Number of teeth along each side of the margin.
+    pub serrations: u32,
+    /// Depth of the teeth, relative to the local half width.
+    pub serration_depth: f32,
+    /// How far the blade halves are folded up along the midrib.
+    pub fold: f32,
+    /// Number of lateral vein pairs branching off the midrib.
+    pub veins: u32,
+    /// Depth of the grooves the midrib and veins press into the blade.
+    pub vein_depth: f32,
+    pub color: Color,
+}
+impl Default for LeafletShape {
+    fn default() -> Self {
+        Self {
+            outline: LeafletOutline::Ovate,
+            width: 0.7,
+            serrations: 9,
+            serration_depth: 0.12,
+            fold: 0.15,
+            veins: 8,
+            vein_depth: 0.008,
+            color: LEAF_GREEN,
+        }
+    }
+}
+
+/// How the leaflets sit at the tip of a petiole.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct LeafSpec {
+    /// Angle between the terminal leaflet and the outermost lateral leaflets, in radians.
+    pub lateral_angle: f32,
+    /// Angle between the petiole and the leaf plane, in radians.
+    pub bend: f32,
+    pub shape: LeafletShape,
+    /// Turn of the hue of every leaf away from the color of the `shape`, in degrees.
+    pub hue: SizeDistribution,
+    /// Lightness of every leaf relative to the color of the `shape`.
+    pub lightness: SizeDistribution,
+}
+impl Default for LeafSpec {
+    fn default() -> Self {
+        Self {
+            lateral_angle: 0.9,
+            bend: 1.0,
+            shape: LeafletShape::default(),
+            hue: SizeDistribution::constant(0.0),
+            lightness: SizeDistribution::constant(1.0),
+        }
+    }
+}
+impl LeafSpec {
+    /// Draws the shape of the leaflets of one leaf, which share a color within the ranges of
+    /// `hue` and `lightness`.
+    pub fn sample_shape(&self, rng: &mut impl Rng) -> LeafletShape {
+        let color = self.shape.color;
+        let hue = (color.h() + self.hue.sample(rng)).rem_euclid(360.0);
+        let lightness = (color.l() * self.lightness.sample(rng)).clamp(0.0, 1.0);
+        LeafletShape {
+            color: color.with_h(hue).with_l(lightness).as_rgba(),
+            ..self.shape.clone()
+        }
+    }
+}
+
+/// A leaflet blade carried by the `midrib` particles, from the petiole tip to the leaflet tip.
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct Leaflet {
+    pub shape: LeafletShape,
+    pub condition: LeafCondition,
+    pub midrib: Vec<Entity>,
+}
+
+const LEAF_GREEN: Color = Color::rgb(0.13, 0.4, 0.1);
+
+/// Fraction of the blade length at the base where the margin has no teeth.
+const ENTIRE_BASE: f32 = 0.3;
+/// How far along the midrib a lateral vein climbs while it crosses the blade.
+const VEIN_SLOPE: f32 = 0.25;
+
+impl LeafletShape {
+    /// Half width of the blade at `t` in `[0, 1]`, relative to the midrib length.
+    pub fn half_width(&self, t: f32) -> f32 {
+        let mut half_width = self.outline.profile(t) * self.width * 0.5;
+        if self.serrations > 0 && t > ENTIRE_BASE {
+            let phase = (t - ENTIRE_BASE) / (1.0 - ENTIRE_BASE) * self.serrations as f32;
+            half_width *= 1.0 - self.serration_depth * phase.fract();
+        }
+        half_width
+    }
+
+    /// Depth of the midrib and vein grooves at blade coordinates `t` along and `s` across.
+    fn vein_groove(&self, t: f32, s: f32) -> f32 {
+        let spacing = 1.0 / (self.veins + 1) as f32;
+        let midrib = (-(s / 0.1).powi(2)).exp();
+        let veins = (1..=self.veins)
+            .map(|k| {
+                let distance = t - (k as f32 * spacing + VEIN_SLOPE * s.abs());
+                (-(distance / (spacing * 0.2)).powi(2)).exp()
+            })
+            .fold(0.0, f32::max);
+        self.vein_depth * midrib.max(veins)
+    }
+
+    /// Triangulates the blade around the `midrib` frames and appends it to `mesh`. The blade
+    /// spreads along each frame's local X axis and faces its local Z axis; UVs map the midrib
+    /// to `u = 0.5` and the base to `v = 0`.
+    ///
+    /// `condition` tints the vertices, curls the blade with mildew, cuts its holes and pulls in
+    /// its torn margins. The UVs stay those of the intact blade, so textures tear with it.
+    pub fn add_blade(&self, mesh: &mut MeshMap, midrib: &[Transform], condition: &LeafCondition) {
+        if midrib.len() < 2 {
+            return;
+        }
+        let mut arc_lengths = vec![0.0];
+        for (a, b) in midrib.iter().tuple_windows() {
+            let length = arc_lengths.last().unwrap() + a.translation.distance(b.translation);
+            arc_lengths.push(length);
+        }
+        let length = *arc_lengths.last().unwrap();
+        if length <= 0.0 {
+            return;
+        }
+
+        let rows = (4 * (self.serrations + 1)).max(6 * (self.veins + 1));
+        // Lesions and holes need a finer grid across the blade to show.
+        let columns = if condition.is_healthy() { 8 } else { 24 };
+        let fold = self.fold + 0.3 * condition.mildew;
+        let grid = (0..=rows)
+            .map(|row| {
+                let t = row as f32 / rows as f32;
+                let (position, rotation) = sample_midrib(midrib, &arc_lengths, t * length);
+                let lateral = rotation * Vec3::X;
+                let normal = rotation * Vec3::Z;
+                let half_width = self.half_width(t);
+                (0..=columns)
+                    .map(|column| {
+                        let u = column as f32 / columns as f32;
+                        let s = u * 2.0 - 1.0;
+                        let point = Vec2::new(s * half_width, t);
+                        let reach = s * half_width * (1.0 - condition.tear(t, s));
+                        let lift = fold * s.abs() * half_width - self.vein_groove(t, s);
+                        let vertex =
+                            mesh.add_vertex(position + (lateral * reach + normal * lift) * length);
+                        mesh.set_uv(vertex, [u, t]);
+                        let edge = s.abs().max(t);
+                        mesh.set_color(vertex, condition.color(self.color, point, edge));
+                        (vertex, point)
+                    })
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        for (a, b) in grid.iter().tuple_windows() {
+            for i in 0..columns {
+                for [p, q, r] in [[a[i], a[i + 1], b[i]], [b[i], a[i + 1], b[i + 1]]] {
+                    if !condition.is_hole((p.1 + q.1 + r.1) / 3.0) {
+                        mesh.add_face((p.0, q.0, r.0));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Interpolates the position and orientation of the `midrib` at `distance` along it.
+fn sample_midrib(midrib: &[Transform], arc_lengths: &[f32], distance: f32) -> (Vec3, Quat) {
+    let segment = arc_lengths
+        .iter()
+        .skip(1)
+        .position(|&end| end >= distance)
+        .unwrap_or(arc_lengths.len() - 2);
+    let (a, b) = (&midrib[segment], &midrib[segment + 1]);
+    let segment_length = arc_lengths[segment + 1] - arc_lengths[segment];
+    let along = if segment_length > 0.0 {
+        ((distance - arc_lengths[segment]) / segment_length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (
+        a.translation.lerp(b.translation, along),
+        a.rotation.slerp(b.rotation, along),
+    )
+}
+
+/// Spawns a petiole off `crown` that ends in `spec.leaflet.count` leaflets fanned out in the
+/// leaf plane.
+pub(crate) fn spawn_leaf(
+    commands: &mut Commands,
+    rng: &mut ChaCha8Rng,
+    name: &str,
+    crown: &StemChain,
+    spec: &PlantSpec,
+    rotation: Quat,
+) -> StemChain {
+    let petiole = spawn_stem_chain(
+        commands,
+        rng,
+        name,
+        OrganKind::Petiole,
+        crown,
+        &spec.petiole,
+        rotation,
+    );
+    spawn_leaflets(commands, rng, name, &petiole, spec);
+    petiole
+}
+
+/// Spawns `spec.leaflet.count` leaflets fanned out in the leaf plane at the tip of `petiole`.
+pub(crate) fn spawn_leaflets(
+    commands: &mut Commands,
+    rng: &mut ChaCha8Rng,
+    name: &str,
+    petiole: &StemChain,
+    spec: &PlantSpec,
+) {
+    let leaflets = spec.leaflet.count.sample_count(rng);
+    let shape = spec.leaf.sample_shape(rng);
+    for l in 0..leaflets {
+        let angle = if leaflets > 1 {
+            spec.leaf.lateral_angle * (2.0 * l as f32 / (leaflets - 1) as f32 - 1.0)
+        } else {
+            0.0
+        };
+        let name = format!("{name} Leaflet {l}");
+        let leaflet = spawn_stem_chain(
+            commands,
+            rng,
+            &name,
+            OrganKind::Leaflet,
+            petiole,
+            &spec.leaflet,
+            Quat::from_rotation_x(spec.leaf.bend) * Quat::from_rotation_z(angle),
+        );
+        let midrib = std::iter::once(petiole.tip)
+            .chain(leaflet.nodes.iter().copied())
+            .collect();
+        commands.entity(leaflet.nodes[0]).insert(Leaflet {
+            condition: spec.leaf_health.sample(rng, &shape),
+            shape: shape.clone(),
+            midrib,
+        });
+    }
+}
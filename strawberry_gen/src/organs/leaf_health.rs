@@ -0,0 +1,287 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{LeafletShape, SizeDistribution};
+
+/// Kind of a lesion on a leaflet blade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum SpotKind {
+    /// Dead tissue: a dark brown patch.
+    Necrotic,
+    /// Common leaf spot: a pale grey centre in a purple ring.
+    LeafSpot,
+}
+
+/// A lesion centred on a point in blade coordinates.
+///
+/// Blade coordinates measure `x` across the blade from the midrib and `y` along the midrib from
+/// the base, both relative to the midrib length.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct LeafSpot {
+    pub kind: SpotKind,
+    pub center: Vec2,
+    pub radius: f32,
+}
+
+/// A hole eaten through the blade, in blade coordinates.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct LeafHole {
+    pub center: Vec2,
+    pub radius: f32,
+}
+
+/// A ragged notch torn into the margin on one side of the midrib.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct MarginTear {
+    /// Where along the midrib the notch is deepest, from 0 at the base to 1 at the tip.
+    pub t: f32,
+    /// -1 for the margin on the negative X side of the blade, 1 for the other one.
+    pub side: f32,
+    /// Extent of the notch along the midrib.
+    pub width: f32,
+    /// Depth of the notch, relative to the local half width.
+    pub depth: f32,
+}
+
+/// Senescence, disease and damage of a single leaflet. The default is a healthy leaflet.
+#[derive(Debug, Clone, Default, PartialEq, Reflect)]
+pub struct LeafCondition {
+    /// Yellowing from 0 (green) to 1 (brown), progressing from the margin and tip inwards.
+    pub senescence: f32,
+    /// Share of the blade covered by powdery mildew, which also curls the blade upwards.
+    pub mildew: f32,
+    pub spots: Vec<LeafSpot>,
+    pub holes: Vec<LeafHole>,
+    pub tears: Vec<MarginTear>,
+    /// Seed of the noise that makes patches and edges irregular.
+    pub seed: u32,
+}
+
+const SENESCENT_YELLOW: Color = Color::rgb(0.75, 0.68, 0.12);
+const SENESCENT_BROWN: Color = Color::rgb(0.4, 0.25, 0.08);
+const NECROTIC_BROWN: Color = Color::rgb(0.22, 0.13, 0.05);
+const LEAF_SPOT_GREY: Color = Color::rgb(0.75, 0.72, 0.65);
+const LEAF_SPOT_PURPLE: Color = Color::rgb(0.35, 0.08, 0.2);
+const MILDEW_WHITE: Color = Color::rgb(0.82, 0.84, 0.8);
+
+impl LeafCondition {
+    pub fn is_healthy(&self) -> bool {
+        self.senescence <= 0.0
+            && self.mildew <= 0.0
+            && self.spots.is_empty()
+            && self.holes.is_empty()
+            && self.tears.is_empty()
+    }
+
+    /// How far the margin at `t` on the side of `s` is torn in, relative to the half width.
+    pub fn tear(&self, t: f32, s: f32) -> f32 {
+        self.tears
+            .iter()
+            .filter(|tear| tear.side * s > 0.0)
+            .map(|tear| {
+                let notch = (1.0 - (t - tear.t).abs() / tear.width).max(0.0);
+                let ragged = 0.6 + 0.4 * self.noise(Vec2::new(t * 60.0, tear.side * 7.0));
+                tear.depth * notch * ragged
+            })
+            .fold(0.0, f32::max)
+            .min(0.95)
+    }
+
+    /// Whether the blade at `point` in blade coordinates has been eaten away.
+    pub fn is_hole(&self, point: Vec2) -> bool {
+        self.holes.iter().any(|hole| {
+            let ragged = 0.75 + 0.5 * self.noise(point * 80.0);
+            point.distance(hole.center) < hole.radius * ragged
+        })
+    }
+
+    /// Color of the blade at `point` in blade coordinates, where the healthy blade has
+    /// `color`. `edge` is 0 at the midrib and base and 1 at the margin and tip.
+    pub fn color(&self, color: Color, point: Vec2, edge: f32) -> [f32; 4] {
+        let mut color = Vec4::from(color.as_linear_rgba_f32());
+        let blend = |color: &mut Vec4, target: Color, amount: f32| {
+            *color = color.lerp(
+                Vec4::from(target.as_linear_rgba_f32()),
+                amount.clamp(0.0, 1.0),
+            );
+        };
+
+        if self.senescence > 0.0 {
+            let mottle = 0.8 + 0.4 * self.noise(point * 12.0);
+            let yellowing = self.senescence * (0.5 + edge) * mottle;
+            blend(&mut color, SENESCENT_YELLOW, 2.0 * yellowing);
+            blend(&mut color, SENESCENT_BROWN, 2.0 * yellowing - 1.2);
+        }
+        if self.mildew > 0.0 {
+            let patches = self.noise(point * 9.0 + 31.0);
+            blend(
+                &mut color,
+                MILDEW_WHITE,
+                (patches - (1.0 - self.mildew)) * 4.0,
+            );
+        }
+        for spot in &self.spots {
+            let distance = point.distance(spot.center) / spot.radius;
+            let distance = distance * (0.85 + 0.3 * self.noise(point * 150.0));
+            match spot.kind {
+                SpotKind::Necrotic => blend(&mut color, NECROTIC_BROWN, (1.2 - distance) * 5.0),
+                SpotKind::LeafSpot => {
+                    blend(&mut color, LEAF_SPOT_PURPLE, (1.4 - distance) * 4.0);
+                    blend(&mut color, LEAF_SPOT_GREY, (0.6 - distance) * 6.0);
+                }
+            }
+        }
+        // Tissue around a hole dries out.
+        for hole in &self.holes {
+            let distance = point.distance(hole.center) / hole.radius;
+            blend(&mut color, NECROTIC_BROWN, (1.4 - distance) * 2.0);
+        }
+        color.into()
+    }
+
+    fn noise(&self, point: Vec2) -> f32 {
+        value_noise(point, self.seed)
+    }
+}
+
+/// Smooth value noise in `[0, 1]` on a unit lattice, varying with `seed`.
+pub(crate) fn value_noise(point: Vec2, seed: u32) -> f32 {
+    let hash = |x: i32, y: i32| {
+        let mut h = (x as u32).wrapping_mul(0x8da6_b343)
+            ^ (y as u32).wrapping_mul(0xd816_3841)
+            ^ seed.wrapping_mul(0xcb1a_b31f);
+        h ^= h >> 13;
+        h = h.wrapping_mul(0x5bd1_e995);
+        h ^= h >> 15;
+        (h & 0xff_ffff) as f32 / 0xff_ffff as f32
+    };
+    let cell = point.floor();
+    let f = point - cell;
+    let f = f * f * (Vec2::splat(3.0) - 2.0 * f);
+    let (x, y) = (cell.x as i32, cell.y as i32);
+    let bottom = hash(x, y) + (hash(x + 1, y) - hash(x, y)) * f.x;
+    let top = hash(x, y + 1) + (hash(x + 1, y + 1) - hash(x, y + 1)) * f.x;
+    bottom + (top - bottom) * f.y
+}
+
+/// How often leaflets of a plant show each kind of senescence, disease and damage. All
+/// probabilities are per leaflet.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct LeafHealthSpec {
+    pub senescence: SizeDistribution,
+    pub spot_probability: f32,
+    pub spots: SizeDistribution,
+    pub spot_radius: SizeDistribution,
+    /// Share of leaf spots that are [`SpotKind::LeafSpot`] rather than necrotic.
+    pub leaf_spot_share: f32,
+    pub mildew_probability: f32,
+    pub mildew: SizeDistribution,
+    pub hole_probability: f32,
+    pub holes: SizeDistribution,
+    pub hole_radius: SizeDistribution,
+    pub tear_probability: f32,
+    pub tears: SizeDistribution,
+    pub tear_depth: SizeDistribution,
+}
+impl Default for LeafHealthSpec {
+    fn default() -> Self {
+        Self {
+            senescence: SizeDistribution::constant(0.0),
+            spot_probability: 0.0,
+            spots: SizeDistribution::new(6.0, 4.0),
+            spot_radius: SizeDistribution::new(0.02, 0.01),
+            leaf_spot_share: 0.5,
+            mildew_probability: 0.0,
+            mildew: SizeDistribution::new(0.4, 0.3),
+            hole_probability: 0.0,
+            holes: SizeDistribution::new(3.0, 2.0),
+            hole_radius: SizeDistribution::new(0.04, 0.02),
+            tear_probability: 0.0,
+            tears: SizeDistribution::new(1.0, 0.5),
+            tear_depth: SizeDistribution::new(0.5, 0.3),
+        }
+    }
+}
+impl LeafHealthSpec {
+    /// Draws the condition of a leaflet with the given `shape`, placing lesions and holes
+    /// on its blade.
+    pub fn sample(&self, rng: &mut impl Rng, shape: &LeafletShape) -> LeafCondition {
+        let mut condition = LeafCondition {
+            senescence: self.senescence.sample(rng).clamp(0.0, 1.0),
+            seed: rng.gen(),
+            ..default()
+        };
+        if rng.gen_bool(self.spot_probability.clamp(0.0, 1.0) as f64) {
+            for _ in 0..self.spots.sample_count(rng) {
+                let kind = if rng.gen_bool(self.leaf_spot_share.clamp(0.0, 1.0) as f64) {
+                    SpotKind::LeafSpot
+                } else {
+                    SpotKind::Necrotic
+                };
+                condition.spots.push(LeafSpot {
+                    kind,
+                    center: point_on_blade(rng, shape),
+                    radius: self.spot_radius.sample(rng).max(0.001),
+                });
+            }
+        }
+        if rng.gen_bool(self.mildew_probability.clamp(0.0, 1.0) as f64) {
+            condition.mildew = self.mildew.sample(rng).clamp(0.0, 1.0);
+        }
+        if rng.gen_bool(self.hole_probability.clamp(0.0, 1.0) as f64) {
+            for _ in 0..self.holes.sample_count(rng) {
+                condition.holes.push(LeafHole {
+                    center: point_on_blade(rng, shape),
+                    radius: self.hole_radius.sample(rng).max(0.001),
+                });
+            }
+        }
+        if rng.gen_bool(self.tear_probability.clamp(0.0, 1.0) as f64) {
+            for _ in 0..self.tears.sample_count(rng) {
+                condition.tears.push(MarginTear {
+                    t: rng.gen_range(0.3..0.95),
+                    side: if rng.gen_bool(0.5) { -1.0 } else { 1.0 },
+                    width: rng.gen_range(0.05..0.15),
+                    depth: self.tear_depth.sample(rng).clamp(0.0, 1.0),
+                });
+            }
+        }
+        condition
+    }
+}
+
+/// Draws a point inside the blade of `shape`, away from its base, tip and margin.
+fn point_on_blade(rng: &mut impl Rng, shape: &LeafletShape) -> Vec2 {
+    let t = rng.gen_range(0.15..0.9);
+    let x = rng.gen_range(-0.8..0.8) * shape.half_width(t);
+    Vec2::new(x, t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MeshMap;
+
+    #[test]
+    fn test_holes_cut_faces() {
+        let shape = LeafletShape::default();
+        let midrib = [Transform::IDENTITY, Transform::from_xyz(0.0, 1.0, 0.0)];
+        let face_count = |condition: &LeafCondition| {
+            let mut mesh = MeshMap::default();
+            shape.add_blade(&mut mesh, &midrib, condition);
+            mesh.face_iter().count()
+        };
+        let yellow = LeafCondition {
+            senescence: 0.5,
+            ..default()
+        };
+        let eaten = LeafCondition {
+            holes: vec![LeafHole {
+                center: Vec2::new(0.0, 0.5),
+                radius: 0.1,
+            }],
+            ..yellow.clone()
+        };
+        assert!(face_count(&eaten) < face_count(&yellow));
+    }
+}
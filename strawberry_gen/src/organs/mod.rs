@@ -0,0 +1,26 @@
+mod leaf;
+pub use leaf::*;
+
+mod leaf_health;
+pub use leaf_health::*;
+
+mod fruit;
+pub use fruit::*;
+
+mod fruit_defect;
+pub use fruit_defect::*;
+
+mod flower;
+pub use flower::*;
+
+mod truss;
+pub use truss::*;
+
+mod crown;
+pub use crown::*;
+
+mod runner;
+pub use runner::*;
+
+mod roots;
+pub use roots::*;
@@ -0,0 +1,189 @@
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    spawn_stem_chain, GrowthSpec, Organ, OrganKind, OrganSpec, Segment, SizeDistribution, StemChain,
+};
+
+/// A fibrous root system growing down into the soil from the plant base.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct RootSpec {
+    /// Number of primary roots. `primary.count` is not used.
+    pub count: SizeDistribution,
+    /// A primary root; its `rotation` makes it wander on the way down.
+    pub primary: OrganSpec,
+    /// Angle of the primary roots away from straight down, in radians.
+    pub spread: SizeDistribution,
+    /// Number of lateral roots on each segment of a primary root. `lateral.count` is not used.
+    pub laterals: SizeDistribution,
+    pub lateral: OrganSpec,
+    /// Angle between a lateral root and its primary root, in radians.
+    pub lateral_angle: SizeDistribution,
+    /// Radius of a primary root at its tip, relative to its radius at the base.
+    pub taper: f32,
+}
+impl Default for RootSpec {
+    fn default() -> Self {
+        Self {
+            count: SizeDistribution::new(10.0, 3.0),
+            primary: OrganSpec::new(
+                SizeDistribution::constant(1.0),
+                6,
+                SizeDistribution::new(1.2, 0.3),
+                SizeDistribution::new(0.012, 0.003),
+            )
+            .with_rotation(SizeDistribution::new(0.2, 0.1)),
+            spread: SizeDistribution::new(0.5, 0.3),
+            laterals: SizeDistribution::new(1.0, 1.0),
+            lateral: OrganSpec::new(
+                SizeDistribution::constant(1.0),
+                3,
+                SizeDistribution::new(0.35, 0.15),
+                SizeDistribution::new(0.005, 0.001),
+            )
+            .with_rotation(SizeDistribution::new(0.2, 0.1)),
+            lateral_angle: SizeDistribution::new(1.0, 0.3),
+            taper: 0.5,
+        }
+    }
+}
+
+/// A stem of the root system, meshed apart from the shoot so renders can hide it.
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct RootStem;
+
+/// Spawns the root system hanging down from `base`. `rotation` turns the whole system
+/// relative to `base`, like the crowns.
+pub(crate) fn spawn_roots(
+    commands: &mut Commands,
+    rng: &mut ChaCha8Rng,
+    base: &StemChain,
+    spec: &RootSpec,
+    growth: &GrowthSpec,
+    rotation: Quat,
+) {
+    let roots = spec.count.sample_count(rng);
+    let azimuth = rng.gen_range(0.0..TAU);
+    let segments = spec.primary.segments.max(1);
+    for r in 0..roots {
+        let name = format!("Root {r}");
+        let azimuth = azimuth + (r as f32 + rng.gen_range(-0.3..0.3)) / roots as f32 * TAU;
+        let mut turn = rotation
+            * Quat::from_rotation_y(azimuth)
+            * Quat::from_rotation_x(PI - spec.spread.sample(rng));
+        let length = spec.primary.length.sample(rng) / segments as f32;
+        let size = spec.primary.size.sample(rng);
+        let organ = Organ::new(OrganKind::Root, &name, base.plant);
+
+        // Spawn the primary root one segment at a time, so that it tapers and carries laterals
+        // at every node.
+        let mut chain = StemChain {
+            nodes: Vec::new(),
+            ..base.clone()
+        };
+        let mut joint_base = base.nodes.len().checked_sub(2).map(|i| base.nodes[i]);
+        for i in 0..segments {
+            let taper = 1.0 - (1.0 - spec.taper) * i as f32 / segments as f32;
+            let previous_tip = chain.tip;
+            let segment = Segment {
+                name: &name,
+                index: i,
+                organ,
+                spec: &spec.primary,
+                size: size * taper,
+                length,
+                rotation: turn,
+            };
+            let particle = chain.push_segment(commands, rng, joint_base, segment);
+            commands.entity(particle).insert(RootStem);
+            joint_base = Some(previous_tip);
+            turn = Quat::IDENTITY;
+
+            for l in 0..spec.laterals.sample_count(rng) {
+                let rotation = Quat::from_rotation_y(rng.gen_range(0.0..TAU))
+                    * Quat::from_rotation_x(spec.lateral_angle.sample(rng));
+                let name = format!("{name} S{i} Lateral {l}");
+                let lateral = spawn_stem_chain(
+                    commands,
+                    rng,
+                    &name,
+                    OrganKind::Root,
+                    &chain,
+                    &spec.lateral,
+                    rotation,
+                );
+                for node in &lateral.nodes {
+                    commands.entity(*node).insert(RootStem);
+                }
+                lateral.delay_growth(commands, growth.plastochron);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{
+        test_utils::{spawn_plant, test_app},
+        ParticlePosition, PlantMeshBuilder, PlantSpec,
+    };
+
+    fn spawn_rooted_plant(app: &mut App) -> Entity {
+        spawn_plant(
+            app,
+            PlantSpec {
+                roots: Some(RootSpec::default()),
+                ..default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_primary_roots_are_single_organs() {
+        let mut app = test_app();
+        let plant = spawn_rooted_plant(&mut app);
+        let roots = app.world.run_system_once(
+            |roots: Query<(&Organ, &ParticlePosition), With<RootStem>>| {
+                roots
+                    .iter()
+                    .map(|(organ, position)| (*organ, position.y))
+                    .collect::<Vec<_>>()
+            },
+        );
+        assert!(!roots.is_empty());
+        assert!(roots.iter().all(|(organ, _)| organ.kind == OrganKind::Root));
+        let first = Organ::new(OrganKind::Root, "Root 0", plant);
+        let first: Vec<f32> = (roots.iter())
+            .filter(|(organ, _)| *organ == first)
+            .map(|(_, y)| *y)
+            .collect();
+        assert_eq!(first.len() as u32, RootSpec::default().primary.segments);
+        assert!(first.iter().all(|y| *y < 0.0));
+    }
+
+    #[test]
+    fn test_roots_have_a_mesh_of_their_own() {
+        let mut app = test_app();
+        let plant = spawn_rooted_plant(&mut app);
+        let meshes = app
+            .world
+            .run_system_once(|builder: PlantMeshBuilder| builder.build());
+        let kinds = |is_root| {
+            let mesh = &meshes[&(plant, is_root)];
+            (mesh.face_iter())
+                .filter_map(|face| mesh.face_label(face)?.organ())
+                .map(|organ| organ.kind)
+                .collect::<Vec<_>>()
+        };
+        let (roots, shoot) = (kinds(true), kinds(false));
+        assert!(!roots.is_empty() && !shoot.is_empty());
+        assert!(roots.iter().all(|kind| *kind == OrganKind::Root));
+        assert!(!shoot.contains(&OrganKind::Root));
+    }
+}
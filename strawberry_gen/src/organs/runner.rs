@@ -0,0 +1,190 @@
+use std::f32::consts::TAU;
+
+use aery::prelude::*;
+use bevy::prelude::*;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    spawn_stem_chain, OrganKind, OrganSpec, PartOf, PlantSpec, SizeDistribution, StemChain,
+    StrawberryPlant, Tropism,
+};
+
+/// Runners (stolons) creeping away from a crown, rooting into daughter plants at their nodes.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct RunnerSpec {
+    /// Number of runners per crown.
+    pub count: SizeDistribution,
+    /// Stolon between the crown and the first daughter plant, and between two daughters. A
+    /// [`Tropism::creep`] bends it down to the ground and along it, so the daughters root in
+    /// the soil.
+    pub internode: OrganSpec,
+    /// Angle of the runner away from the vertical as it leaves the crown, in radians.
+    pub elevation: SizeDistribution,
+    /// Number of daughter plants along each runner.
+    pub daughters: u32,
+    /// Size of a daughter plant's organs relative to the mother plant's.
+    pub daughter_scale: f32,
+}
+impl Default for RunnerSpec {
+    fn default() -> Self {
+        Self {
+            count: SizeDistribution::new(1.0, 1.0),
+            internode: OrganSpec::new(
+                SizeDistribution::constant(1.0),
+                5,
+                SizeDistribution::new(2.5, 0.5),
+                SizeDistribution::new(0.015, 0.003),
+            )
+            .with_rotation(SizeDistribution::new(0.04, 0.04))
+            .with_tropism(Tropism::default().with_creep(true)),
+            elevation: SizeDistribution::new(1.45, 0.05),
+            daughters: 2,
+            daughter_scale: 0.6,
+        }
+    }
+}
+impl RunnerSpec {
+    /// Spec of a young daughter plant of `mother`: smaller, without trusses or runners of its
+    /// own.
+    pub fn daughter_spec(&self, mother: &PlantSpec, rng: &mut impl Rng) -> PlantSpec {
+        let scale = self.daughter_scale;
+        let mut spec = mother.clone();
+        spec.seed = rng.gen();
+        spec.crown.count = SizeDistribution::constant(1.0);
+        spec.petiole = mother.petiole.scaled(scale);
+        spec.petiole.count = mother.petiole.count.scaled(scale);
+        spec.leaflet = mother.leaflet.scaled(scale);
+        spec.truss.count = SizeDistribution::constant(0.0);
+        spec.runner.count = SizeDistribution::constant(0.0);
+        spec
+    }
+}
+
+/// Roots a [`StrawberryPlant`] on a runner node instead of in its own spot, so it shares the
+/// physics graph of its mother plant. The node particle becomes the daughter's
+/// [`PlantRoot`](crate::PlantRoot), and the daughter's crowns are turned back upright relative
+/// to `frame`, the rest frame of the runner at the node.
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct RunnerAnchor {
+    pub particle: Entity,
+    pub constraint: Option<Entity>,
+    pub frame: Quat,
+}
+
+/// Spawns the runners of `crown`, with a daughter [`StrawberryPlant`] anchored at every node.
+/// The daughters are generated like any other plant, on the next run of `init_plant`.
+pub(crate) fn spawn_runners(
+    commands: &mut Commands,
+    rng: &mut ChaCha8Rng,
+    name: &str,
+    crown: &StemChain,
+    spec: &PlantSpec,
+) {
+    let runner = &spec.runner;
+    for r in 0..runner.count.sample_count(rng) {
+        let name = format!("{name} Runner {r}");
+        let bend = Quat::from_rotation_y(rng.gen_range(0.0..TAU))
+            * Quat::from_rotation_x(runner.elevation.sample(rng));
+        let mut node = crown.clone();
+        for d in 0..runner.daughters {
+            let rotation = if d == 0 { bend } else { Quat::IDENTITY };
+            let internode_name = format!("{name} Internode {d}");
+            node = spawn_stem_chain(
+                commands,
+                rng,
+                &internode_name,
+                OrganKind::Runner,
+                &node,
+                &runner.internode,
+                rotation,
+            );
+            let delay = if d == 0 {
+                spec.growth.runner_day
+            } else {
+                spec.growth.elongation_days
+            };
+            node.delay_growth(commands, delay);
+            commands
+                .spawn((
+                    Name::new(format!("{name} Daughter {d}")),
+                    StrawberryPlant,
+                    runner.daughter_spec(spec, rng),
+                    RunnerAnchor {
+                        particle: node.tip,
+                        constraint: node.tip_constraint,
+                        frame: node.frame,
+                    },
+                ))
+                .set::<PartOf>(crown.plant);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{
+        test_utils::{spawn_plant, test_app},
+        Growth, Organ, ParticlePosition, PlantAge, PlantRoot,
+    };
+
+    #[test]
+    fn test_daughters_root_on_the_ground() {
+        let mut spec = PlantSpec::default();
+        spec.runner.count = SizeDistribution::constant(2.0);
+        let mut app = test_app();
+        spawn_plant(&mut app, (spec, Transform::from_xyz(1.0, 0.5, -2.0)));
+
+        app.world.run_system_once(
+            |anchors: Query<(Entity, &RunnerAnchor)>,
+             particles: Query<&ParticlePosition>,
+             crowns: Query<(&Organ, &Transform)>| {
+                assert_eq!(anchors.iter().len(), 4);
+                for (daughter, anchor) in &anchors {
+                    let node = particles.get(anchor.particle).unwrap();
+                    assert!((node.y - 0.5).abs() < 0.01, "node at {node:?}");
+                    // The daughter's crowns grow upright from the node.
+                    let crowns = crowns
+                        .iter()
+                        .filter(|(organ, _)| {
+                            organ.plant == daughter && organ.kind == OrganKind::Crown
+                        })
+                        .collect::<Vec<_>>();
+                    assert!(!crowns.is_empty());
+                    for (_, transform) in crowns {
+                        assert!((transform.rotation * Vec3::Y).y > 0.8);
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_daughters_are_plants_of_their_own() {
+        let mut app = test_app();
+        let mother = spawn_plant(&mut app, (PlantSpec::default(), PlantAge::new(100.0)));
+        app.update();
+
+        let daughters: Vec<Entity> = app.world.run_system_once(
+            move |daughters: Query<(Entity, &RunnerAnchor, &PlantAge)>,
+                  roots: Query<&PlantRoot>,
+                  nodes: Query<&Growth>| {
+                assert!(!daughters.is_empty());
+                daughters
+                    .iter()
+                    .map(|(daughter, anchor, age)| {
+                        assert_eq!(roots.get(anchor.particle).unwrap().0, daughter);
+                        let emergence = nodes.get(anchor.particle).unwrap().emergence;
+                        assert!(emergence > 0.0);
+                        assert_eq!(age.days, 100.0 - emergence);
+                        daughter
+                    })
+                    .collect::<Vec<_>>()
+            },
+        );
+        assert!(daughters.iter().all(|daughter| *daughter != mother));
+    }
+}
@@ -0,0 +1,169 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    spawn_stem_chain, Flower, FlowerState, OrganKind, OrganSpec, PlantSpec, SizeDistribution,
+    StemChain, Tropism,
+};
+
+/// Branching of a truss, modelled as a dichasial cyme: every node carries a pedicel with a
+/// flower or fruit and `branches` internodes that each end in a node of the next order.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct TrussSpec {
+    /// Deepest branching order. With 1 the peduncle only bears the primary flower.
+    pub orders: u32,
+    /// Number of internodes branching off each node below the last order.
+    pub branches: SizeDistribution,
+    /// Angle between a branching internode and its parent axis, in radians.
+    pub branch_angle: SizeDistribution,
+    /// Axis between two branching nodes of the second order and above.
+    pub internode: OrganSpec,
+    /// Stalk between a node and its flower or fruit.
+    pub pedicel: OrganSpec,
+    /// Length factor applied to internodes and pedicels with each order.
+    pub length_decay: f32,
+    /// Maturity lost with each order, since later flowers open later. Flowers whose maturity
+    /// would drop below zero have not set fruit yet, and are still buds below minus one step.
+    pub maturity_step: f32,
+}
+impl Default for TrussSpec {
+    fn default() -> Self {
+        Self {
+            orders: 3,
+            branches: SizeDistribution::constant(2.0),
+            branch_angle: SizeDistribution::new(0.5, 0.1),
+            internode: OrganSpec::new(
+                SizeDistribution::constant(1.0),
+                2,
+                SizeDistribution::new(0.4, 0.1),
+                SizeDistribution::new(0.018, 0.003),
+            )
+            .with_rotation(SizeDistribution::new(0.1, 0.05))
+            .with_tropism(Tropism::new(0.0, 0.0, 0.02)),
+            pedicel: OrganSpec::new(
+                SizeDistribution::constant(1.0),
+                1,
+                SizeDistribution::new(0.35, 0.08),
+                SizeDistribution::new(0.012, 0.002),
+            )
+            .with_tropism(Tropism::new(0.0, 0.0, 0.02)),
+            length_decay: 0.75,
+            maturity_step: 0.25,
+        }
+    }
+}
+
+/// Spawns a truss off `crown`: the peduncle followed by its branching pedicels, each ending in
+/// a flower, which may have set fruit.
+pub(crate) fn spawn_truss(
+    commands: &mut Commands,
+    rng: &mut ChaCha8Rng,
+    name: &str,
+    crown: &StemChain,
+    spec: &PlantSpec,
+    rotation: Quat,
+) -> StemChain {
+    let peduncle = spawn_stem_chain(
+        commands,
+        rng,
+        name,
+        OrganKind::Peduncle,
+        crown,
+        &spec.truss,
+        rotation,
+    );
+    spawn_inflorescence(commands, rng, name, &peduncle, spec);
+    peduncle
+}
+
+/// Spawns the branching pedicels and flowers of a truss at the tip of `peduncle`.
+pub(crate) fn spawn_inflorescence(
+    commands: &mut Commands,
+    rng: &mut ChaCha8Rng,
+    name: &str,
+    peduncle: &StemChain,
+    spec: &PlantSpec,
+) {
+    let maturity = spec.fruit.maturity.sample(rng);
+    spawn_cyme(commands, rng, name, peduncle, spec, 1, maturity);
+}
+
+/// Puts a flower on `tip`, and the fruit it has set if `maturity` is not below zero.
+pub(crate) fn spawn_blossom(
+    commands: &mut Commands,
+    rng: &mut ChaCha8Rng,
+    tip: Entity,
+    spec: &PlantSpec,
+    maturity: f32,
+) {
+    let fruit = spec.fruit.sample_with_maturity(rng, maturity);
+    let flower = Flower {
+        shape: spec.flower.sample(rng),
+        state: FlowerState::from_bloom(1.0 + maturity / spec.inflorescence.maturity_step),
+        fruit: fruit.shape.clone(),
+        growth: 1.0,
+    };
+    let mut tip = commands.entity(tip);
+    tip.insert(flower);
+    if maturity >= 0.0 {
+        tip.insert(fruit);
+    }
+}
+
+fn spawn_cyme(
+    commands: &mut Commands,
+    rng: &mut ChaCha8Rng,
+    name: &str,
+    node: &StemChain,
+    spec: &PlantSpec,
+    order: u32,
+    maturity: f32,
+) {
+    let truss = &spec.inflorescence;
+    let scale = truss.length_decay.powi(order as i32 - 1);
+
+    let pedicel = spawn_stem_chain(
+        commands,
+        rng,
+        &format!("{name} Pedicel"),
+        OrganKind::Pedicel,
+        node,
+        &truss.pedicel.scaled(scale),
+        Quat::IDENTITY,
+    );
+    spawn_blossom(commands, rng, pedicel.tip, spec, maturity);
+
+    if order >= truss.orders {
+        return;
+    }
+    let branches = truss.branches.sample_count(rng);
+    let azimuth = rng.gen_range(0.0..TAU);
+    for b in 0..branches {
+        let azimuth = azimuth + b as f32 / branches as f32 * TAU;
+        let rotation =
+            Quat::from_rotation_y(azimuth) * Quat::from_rotation_z(truss.branch_angle.sample(rng));
+        let name = format!("{name} Branch {b}");
+        let internode = spawn_stem_chain(
+            commands,
+            rng,
+            &name,
+            OrganKind::Peduncle,
+            node,
+            &truss.internode.scaled(scale),
+            rotation,
+        );
+        internode.delay_growth(commands, spec.growth.order_days);
+        spawn_cyme(
+            commands,
+            rng,
+            &name,
+            &internode,
+            spec,
+            order + 1,
+            maturity - truss.maturity_step,
+        );
+    }
+}
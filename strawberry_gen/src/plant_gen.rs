@@ -1,16 +1,23 @@
 use aery::prelude::*;
-use bevy::asset::AssetLoader;
-use bevy::ecs::system::{Command, EntityCommand, RunSystemOnce};
 use bevy::prelude::*;
-use iter_tools::Itertools;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 use crate::{
-    ConstraintToConstraint, EdgeConstraint, MeshMap, ParticleBundle, ParticlePosition, P0, P1,
+    creep_towards_ground, growth_systems, spawn_crowns, spawn_grammar, spawn_roots, spawn_skeleton,
+    tropism_systems, BendConstraint, ConstraintToConstraint, Cultivar, Dormant, EdgeConstraint,
+    Flower, Fruit, Growth, GrowthDelay, Leaflet, MeasuredSkeleton, Organ, OrganKind, OrganSpec,
+    ParticleBundle, ParticlePosition, PlantAge, PlantGrammar, PlantRoot, PlantSpec, RootStem,
+    RunnerAnchor, Tropism, P0, P1, P2,
 };
 
 #[derive(Component, Debug)]
 pub struct StrawberryPlant;
 
+/// A stem segment ending in this particle, hanging off its `AxisUp` parent.
+///
+/// `rotation` turns the segment relative to the frame of its parent. The accumulated frame is
+/// kept in the particle's `Transform::rotation`, with the segment running along its local Y axis.
 #[derive(Component, Debug)]
 pub struct Stem {
     pub size: f32,
@@ -40,56 +47,308 @@ impl Stem {
 #[derive(Relation)]
 pub struct AxisUp;
 
-fn init_plant(mut commands: Commands, plants: Query<Entity, Changed<StrawberryPlant>>) {
-    for plant in &plants {
-        let p3 = commands
-            .spawn((
-                Name::new("P3"),
-                Stem::simple().with_length(1.3),
-                ParticleBundle::default(),
-            ))
-            .id();
-        let p2 = commands
-            .spawn((
-                Name::new("P2"),
-                Stem::simple().with_length(1.2),
-                ParticleBundle::default(),
-            ))
-            .set::<AxisUp>(p3)
-            .id();
-        let p1 = commands
+/// Links a generated entity to the [`StrawberryPlant`] that owns it, including the daughter
+/// plants on its runners. Despawning a plant with `checked_despawn` despawns all its parts.
+#[derive(Relation)]
+#[aery(Recursive)]
+pub struct PartOf;
+
+/// The particles of a spawned stem chain, its last particle and the constraint that ends in it,
+/// and the plant whose organs hang off it.
+///
+/// `frame` is the rest frame of the tip in the world and `height` its height above the base of
+/// the plant, before the stems are bent by their tropisms other than [`Tropism::creep`].
+#[derive(Clone)]
+pub(crate) struct StemChain {
+    pub nodes: Vec<Entity>,
+    pub tip: Entity,
+    pub tip_constraint: Option<Entity>,
+    pub plant: Entity,
+    pub frame: Quat,
+    pub height: f32,
+}
+impl StemChain {
+    /// Makes the chain emerge `days` after the stem it hangs off.
+    pub fn delay_growth(&self, commands: &mut Commands, days: f32) {
+        commands.entity(self.nodes[0]).insert(GrowthDelay(days));
+    }
+
+    /// Spawns `segment` at the tip of the chain and makes it the new tip, with an
+    /// `EdgeConstraint` to the old tip and a `BendConstraint` over the old tip from
+    /// `joint_base`, the particle before it, if any. Returns the new particle.
+    pub fn push_segment(
+        &mut self,
+        commands: &mut Commands,
+        rng: &mut ChaCha8Rng,
+        joint_base: Option<Entity>,
+        segment: Segment,
+    ) -> Entity {
+        let Segment {
+            name,
+            index: i,
+            organ,
+            spec,
+            size,
+            length,
+            rotation,
+        } = segment;
+        let lean_axis = Quat::from_rotation_y(rng.gen_range(0.0..std::f32::consts::TAU)) * Vec3::X;
+        let lean = Quat::from_axis_angle(lean_axis, spec.rotation.sample(rng));
+        let mut frame = self.frame * rotation * lean;
+        if spec.tropism.creep {
+            frame = creep_towards_ground(frame * Vec3::Y, length, self.height) * frame;
+        }
+        let rotation = self.frame.inverse() * frame;
+        let particle = commands
             .spawn((
-                Name::new("P1"),
-                Stem::simple().with_length(1.1),
+                Name::new(format!("{name} P{i}")),
+                Stem::new(size, length, rotation),
+                Growth::new(size, length),
+                organ,
                 ParticleBundle::default(),
             ))
-            .set::<AxisUp>(p2)
-            .id();
-        let constrain1 = commands
-            .spawn((Name::new("C1"), EdgeConstraint::from_rest_length(1.1)))
-            .set::<P0>(p1)
-            .set::<P1>(p2)
+            .set::<AxisUp>(self.tip)
+            .set::<PartOf>(self.plant)
             .id();
-        commands
-            .spawn((Name::new("C2"), EdgeConstraint::from_rest_length(1.2)))
-            .set::<P0>(p2)
-            .set::<P1>(p3)
-            .set::<ConstraintToConstraint>(constrain1);
+        if spec.tropism != Tropism::default() {
+            commands.entity(particle).insert(spec.tropism);
+        }
+        if let Some(joint_base) = joint_base {
+            commands
+                .spawn((
+                    Name::new(format!("{name} B{i}")),
+                    BendConstraint::from_rest_angle(0.0),
+                ))
+                .set::<P0>(joint_base)
+                .set::<P1>(self.tip)
+                .set::<P2>(particle)
+                .set::<PartOf>(self.plant);
+        }
+        let mut constraint = commands.spawn((
+            Name::new(format!("{name} C{i}")),
+            EdgeConstraint::from_rest_length(length),
+        ));
+        constraint
+            .set::<P0>(self.tip)
+            .set::<P1>(particle)
+            .set::<PartOf>(self.plant);
+        if let Some(previous) = self.tip_constraint {
+            constraint.set::<ConstraintToConstraint>(previous);
+        }
+        self.nodes.push(particle);
+        self.tip = particle;
+        self.tip_constraint = Some(constraint.id());
+        self.height += (frame * Vec3::Y).y * length;
+        self.frame = frame;
+        particle
+    }
+}
+
+/// A segment added to a [`StemChain`] by [`StemChain::push_segment`].
+pub(crate) struct Segment<'a> {
+    /// Name of the organ, which the particle and constraint names start with.
+    pub name: &'a str,
+    /// Position of the segment in its organ.
+    pub index: u32,
+    pub organ: Organ,
+    /// The random lean and the tropism of the segment.
+    pub spec: &'a OrganSpec,
+    pub size: f32,
+    pub length: f32,
+    /// Turn away from the tip of the chain, on top of the random lean.
+    pub rotation: Quat,
+}
+
+/// Spawns the particle a plant grows from, placed and oriented like the plant.
+fn spawn_root(commands: &mut Commands, plant: Entity, transform: Transform) -> StemChain {
+    let tip = commands
+        .spawn((
+            Name::new(format!("Plant {plant:?}")),
+            // The base has no segment of its own, so nothing is drawn around it.
+            Stem::simple().with_size(0.0),
+            ParticleBundle {
+                position: ParticlePosition(transform.translation),
+                transform,
+            },
+            PlantRoot(plant),
+            Organ::new(OrganKind::Crown, "Plant", plant),
+        ))
+        .set::<PartOf>(plant)
+        .id();
+    StemChain {
+        nodes: vec![tip],
+        tip,
+        tip_constraint: None,
+        plant,
+        frame: transform.rotation,
+        height: 0.0,
     }
 }
 
+/// Spawns `organ.segments` stems hanging off `base` along `AxisUp`, with an `EdgeConstraint`
+/// between each pair of neighbouring particles. `rotation` turns the first segment away from
+/// `base`, on top of the random lean every segment gets, and a creeping organ is then turned
+/// down to the ground. The stems are spawned at full size, which their [`Growth`] remembers.
+/// All particles belong to one [`Organ`] of `kind`, whose id follows from `name`.
+pub(crate) fn spawn_stem_chain(
+    commands: &mut Commands,
+    rng: &mut ChaCha8Rng,
+    name: &str,
+    kind: OrganKind,
+    base: &StemChain,
+    organ: &OrganSpec,
+    rotation: Quat,
+) -> StemChain {
+    let segments = organ.segments.max(1);
+    let length = organ.length.sample(rng) / segments as f32;
+    let size = organ.size.sample(rng);
+    let mut chain = StemChain {
+        nodes: Vec::new(),
+        ..base.clone()
+    };
+    let organ_tag = Organ::new(kind, name, base.plant);
+    let mut joint_base = base.nodes.len().checked_sub(2).map(|i| base.nodes[i]);
+    for i in 0..segments {
+        let previous_tip = chain.tip;
+        chain.push_segment(
+            commands,
+            rng,
+            joint_base,
+            Segment {
+                name,
+                index: i,
+                organ: organ_tag,
+                spec: organ,
+                size,
+                length,
+                rotation: if i == 0 { rotation } else { Quat::IDENTITY },
+            },
+        );
+        joint_base = Some(previous_tip);
+    }
+    chain
+}
+
+#[allow(clippy::type_complexity)]
+fn init_plant(
+    mut commands: Commands,
+    plants: Query<
+        (
+            Entity,
+            Option<&PlantSpec>,
+            Option<&Cultivar>,
+            Option<&PlantGrammar>,
+            Option<&MeasuredSkeleton>,
+            Option<&RunnerAnchor>,
+            Option<&Transform>,
+        ),
+        Changed<StrawberryPlant>,
+    >,
+) {
+    for (plant, spec, cultivar, grammar, skeleton, anchor, transform) in &plants {
+        // Plants of a cultivar without a spec of their own each draw from their own seed.
+        let default_spec = cultivar.map_or_else(PlantSpec::default, |cultivar| PlantSpec {
+            seed: plant.to_bits(),
+            ..cultivar.spec()
+        });
+        let spec = spec.unwrap_or(&default_spec);
+        let rng = &mut ChaCha8Rng::seed_from_u64(spec.seed);
+        let (base, rotation) = match anchor {
+            Some(anchor) => {
+                commands.entity(anchor.particle).insert(PlantRoot(plant));
+                let frame = anchor.frame;
+                (
+                    StemChain {
+                        nodes: vec![anchor.particle],
+                        tip: anchor.particle,
+                        tip_constraint: anchor.constraint,
+                        plant,
+                        frame,
+                        // The runner has crept down to the ground by the node.
+                        height: 0.0,
+                    },
+                    frame.inverse() * Quat::from_rotation_arc(frame * Vec3::Y, Vec3::Y) * frame,
+                )
+            }
+            None => (
+                spawn_root(&mut commands, plant, transform.copied().unwrap_or_default()),
+                Quat::IDENTITY,
+            ),
+        };
+        match (skeleton, grammar) {
+            (Some(skeleton), _) => {
+                spawn_skeleton(&mut commands, rng, &base, spec, skeleton, rotation)
+            }
+            (None, Some(grammar)) => {
+                spawn_grammar(&mut commands, rng, &base, spec, grammar, rotation)
+            }
+            (None, None) => spawn_crowns(&mut commands, rng, &base, spec, rotation),
+        }
+        let measured_roots = skeleton.is_some_and(|skeleton| {
+            (skeleton.nodes().iter()).any(|node| node.organ == Some(OrganKind::Root))
+        });
+        if let (Some(roots), false) = (&spec.roots, measured_roots) {
+            spawn_roots(&mut commands, rng, &base, roots, &spec.growth, rotation);
+        }
+    }
+}
+
+fn stems_added(stems: Query<(), Added<Stem>>) -> bool {
+    !stems.is_empty()
+}
+
+/// Places the newly spawned stems in their rest pose at the end of their parent. Stems that
+/// grow or bend later keep their particles, which the constraints pull to their new rest
+/// lengths and angles.
+#[allow(clippy::type_complexity)]
 fn update_stem_transforms(
     // Orient the tree so the `Root`s are in the soil.
     // Aery tracks `Root<R>`, `Branch<R>`, `Leaf<R>` (s) for you
     roots: Query<Entity, Root<AxisUp>>,
-    mut plants: Query<((&mut ParticlePosition, &Stem), Relations<AxisUp>)>,
+    mut plants: Query<(
+        (&mut ParticlePosition, &mut Transform, Ref<Stem>),
+        Relations<AxisUp>,
+    )>,
 ) {
     plants
         .traverse_mut::<AxisUp>(roots.iter())
         .track_self()
-        .for_each(|(prev_pos, _), _, (this_pos, stem), _| {
-            this_pos.0 = prev_pos.0 + Vec3::Y * stem.length;
-        });
+        .for_each(
+            |(prev_pos, prev_transform, _), _, (this_pos, this_transform, stem), _| {
+                if !stem.is_added() {
+                    return;
+                }
+                this_transform.rotation = prev_transform.rotation * stem.rotation;
+                this_pos.0 = prev_pos.0 + this_transform.rotation * Vec3::Y * stem.length;
+                this_transform.translation = this_pos.0;
+            },
+        );
+}
+
+/// Turns the frame of every stem so its Y axis follows the segment between the particles, while
+/// keeping the twist of the rest frame built from `Stem::rotation`.
+#[allow(clippy::type_complexity)]
+fn update_stem_frames(
+    roots: Query<Entity, Root<AxisUp>>,
+    mut stems: Query<(
+        (&ParticlePosition, &mut Transform, &Stem),
+        Relations<AxisUp>,
+    )>,
+) {
+    stems
+        .traverse_mut::<AxisUp>(roots.iter())
+        .track_self()
+        .for_each(
+            |(parent_pos, parent_transform, _), _, (this_pos, this_transform, stem), _| {
+                let rest = parent_transform.rotation * stem.rotation;
+                let direction = (this_pos.0 - parent_pos.0).normalize_or_zero();
+                this_transform.rotation = if direction == Vec3::ZERO {
+                    rest
+                } else {
+                    Quat::from_rotation_arc(rest * Vec3::Y, direction) * rest
+                };
+            },
+        );
 }
 
 fn print_stems(
@@ -106,6 +365,187 @@ fn print_stems(
 pub struct StrawberryPlantPlugin;
 impl Plugin for StrawberryPlantPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, init_plant);
+        app.register_type::<PlantSpec>()
+            .register_type::<Leaflet>()
+            .register_type::<Fruit>()
+            .register_type::<Flower>()
+            .register_type::<RunnerAnchor>()
+            .register_type::<Cultivar>()
+            .register_type::<RootStem>()
+            .register_type::<PlantAge>()
+            .register_type::<Growth>()
+            .register_type::<GrowthDelay>()
+            .register_type::<Dormant>()
+            .register_type::<Organ>()
+            .register_type::<Tropism>()
+            .add_systems(
+                Update,
+                (
+                    init_plant,
+                    apply_deferred,
+                    tropism_systems(),
+                    growth_systems(),
+                    update_stem_transforms.run_if(stems_added),
+                    update_stem_frames,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{spawn_plant, test_app};
+    use bevy::ecs::system::RunSystemOnce;
+    use iter_tools::Itertools;
+
+    fn generate(spec: PlantSpec) -> Vec<(f32, f32, Quat, Vec3)> {
+        let mut app = test_app();
+        spawn_plant(&mut app, spec);
+        app.world
+            .run_system_once(|stems: Query<(Entity, &Stem, &ParticlePosition)>| {
+                stems
+                    .iter()
+                    .sorted_by_key(|(entity, _, _)| *entity)
+                    .map(|(_, stem, position)| (stem.size, stem.length, stem.rotation, position.0))
+                    .collect()
+            })
+    }
+
+    #[test]
+    fn test_same_seed_same_plant() {
+        let spec = PlantSpec {
+            seed: 42,
+            ..default()
+        };
+        let a = generate(spec.clone());
+        let b = generate(spec);
+        assert!(a.iter().any(|(_, _, _, position)| position.y > 0.0));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_different_seed_different_plant() {
+        let a = generate(PlantSpec {
+            seed: 1,
+            ..default()
+        });
+        let b = generate(PlantSpec {
+            seed: 2,
+            ..default()
+        });
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_every_stem_is_tagged_with_a_stable_organ() {
+        let organs = || {
+            let mut app = test_app();
+            let plant = spawn_plant(&mut app, PlantSpec::default());
+            app.world.run_system_once(
+                move |stems: Query<(&Stem, Option<&Organ>)>,
+                      daughters: Query<(), With<RunnerAnchor>>| {
+                    stems
+                        .iter()
+                        .map(|(_, organ)| {
+                            let organ = organ.expect("every stem belongs to an organ");
+                            assert!(organ.plant == plant || daughters.contains(organ.plant));
+                            (organ.kind, organ.id)
+                        })
+                        .sorted()
+                        .collect_vec()
+                },
+            )
+        };
+        let a = organs();
+        assert!(a.iter().any(|(kind, _)| *kind == OrganKind::Pedicel));
+        assert!(a.iter().any(|(kind, _)| *kind == OrganKind::Leaflet));
+        assert_eq!(a, organs());
+    }
+
+    #[test]
+    fn test_despawning_a_plant_despawns_its_parts() {
+        let mut app = test_app();
+        let spawn = |app: &mut App, x: f32| {
+            spawn_plant(
+                app,
+                (PlantSpec::default(), Transform::from_xyz(x, 0.0, 0.0)),
+            )
+        };
+        let a = spawn(&mut app, -1.0);
+        let b = spawn(&mut app, 1.0);
+        let parts = |app: &mut App| {
+            app.world.run_system_once(
+                |parts: Query<(), (Participates<PartOf>, Without<StrawberryPlant>)>| {
+                    parts.iter().count()
+                },
+            )
+        };
+        let before = parts(&mut app);
+
+        app.world.entity_mut(a).checked_despawn();
+        let after = parts(&mut app);
+        assert!(after > 0 && after < before);
+        assert!(app.world.get_entity(b).is_some());
+        let plants = app
+            .world
+            .run_system_once(|stems: Query<&Organ, With<Stem>>| {
+                stems.iter().map(|organ| organ.plant).collect_vec()
+            });
+        assert!(!plants.contains(&a));
+        assert!(plants.contains(&b));
+    }
+
+    #[test]
+    fn test_stem_rotation_accumulates() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, Aery));
+        let root = app
+            .world
+            .spawn((Stem::simple(), ParticleBundle::default()))
+            .id();
+        let rotation = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+        let bent = app
+            .world
+            .spawn((Stem::new(1.0, 1.0, rotation), ParticleBundle::default()))
+            .set::<AxisUp>(root)
+            .id();
+        let straight = app
+            .world
+            .spawn((Stem::simple(), ParticleBundle::default()))
+            .set::<AxisUp>(bent)
+            .id();
+        app.world.run_system_once(update_stem_transforms);
+
+        let position = |entity| app.world.get::<ParticlePosition>(entity).unwrap().0;
+        assert!(position(bent).abs_diff_eq(Vec3::NEG_X, 1e-6));
+        assert!(position(straight).abs_diff_eq(Vec3::NEG_X * 2.0, 1e-6));
+    }
+
+    #[test]
+    fn test_stem_frames_follow_particles() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, Aery));
+        let root = app
+            .world
+            .spawn((Stem::simple(), ParticleBundle::default()))
+            .id();
+        let rotation = Quat::from_rotation_y(0.3);
+        let tip = app
+            .world
+            .spawn((Stem::new(1.0, 1.0, rotation), ParticleBundle::new(Vec3::X)))
+            .set::<AxisUp>(root)
+            .id();
+        app.world.run_system_once(update_stem_frames);
+
+        let frame = app.world.get::<Transform>(tip).unwrap().rotation;
+        assert!((frame * Vec3::Y).abs_diff_eq(Vec3::X, 1e-6));
+
+        // Back at its rest position the stem gets its rest frame, twist included.
+        app.world.get_mut::<ParticlePosition>(tip).unwrap().0 = Vec3::Y;
+        app.world.run_system_once(update_stem_frames);
+        let frame = app.world.get::<Transform>(tip).unwrap().rotation;
+        assert!(frame.abs_diff_eq(rotation, 1e-6));
     }
 }
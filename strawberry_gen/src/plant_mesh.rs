@@ -0,0 +1,154 @@
+use aery::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use iter_tools::Itertools;
+
+use crate::{
+    AxisUp, Dormant, Flower, Fruit, Leaflet, MeshMap, Organ, OrganKind, RootStem, Stem, VertexId,
+};
+
+/// Cosine of the angle above which a stem gets its own tube instead of continuing its parent's.
+const BRANCH_COS: f32 = 0.87;
+
+/// Sides of the tube around a stem.
+const RING_RESOLUTION: usize = 6;
+
+/// Builds the meshes of the plants from their stems, leaflets, flowers and fruits. Every face
+/// is labelled with its [`Organ`]. [`Dormant`] stems and what they bear are left out.
+#[allow(clippy::type_complexity)]
+#[derive(SystemParam)]
+pub struct PlantMeshBuilder<'w, 's> {
+    roots: Query<'w, 's, Entity, Root<AxisUp>>,
+    stems: Query<
+        'w,
+        's,
+        (
+            (
+                Entity,
+                &'static Stem,
+                &'static Transform,
+                Has<RootStem>,
+                Option<&'static Organ>,
+                Has<Dormant>,
+            ),
+            Relations<AxisUp>,
+        ),
+    >,
+    leaflets: Query<'w, 's, (&'static Leaflet, Option<&'static Organ>), Without<Dormant>>,
+    flowers: Query<
+        'w,
+        's,
+        (&'static Flower, &'static Transform, Option<&'static Organ>),
+        Without<Dormant>,
+    >,
+    fruits: Query<
+        'w,
+        's,
+        (&'static Fruit, &'static Transform, Option<&'static Organ>),
+        Without<Dormant>,
+    >,
+    transforms: Query<'w, 's, &'static Transform>,
+}
+
+impl PlantMeshBuilder<'_, '_> {
+    /// Every plant gets a mesh for its shoot and one for its roots, keyed by the plant and
+    /// whether they hold roots.
+    pub fn build(&self) -> HashMap<(Entity, bool), MeshMap> {
+        let mut plant_meshes = HashMap::<(Entity, bool), MeshMap>::new();
+        let stem_color = Color::rgb(0.25, 0.5, 0.15).as_linear_rgba_f32();
+        let root_color = Color::rgb(0.85, 0.78, 0.6).as_linear_rgba_f32();
+        // Rings are kept per mesh, so that roots never continue a tube of the shoot and
+        // daughter plants never continue a tube of their mother.
+        let mut rings = HashMap::<(Entity, Entity, bool), Vec<VertexId>>::new();
+        let add_ring = |mesh: &mut MeshMap, stem: &Stem, transform: &Transform, is_root: bool| {
+            (0..RING_RESOLUTION)
+                .map(|i| {
+                    let angle = i as f32 / RING_RESOLUTION as f32 * std::f32::consts::TAU;
+                    let relative_transform = Transform::from_rotation(Quat::from_rotation_y(angle))
+                        * Transform::from_translation(Vec3::X * stem.size);
+                    let transform = *transform * relative_transform;
+                    let vertex = mesh.add_vertex(transform.translation);
+                    mesh.set_color(vertex, if is_root { root_color } else { stem_color });
+                    vertex
+                })
+                .collect_vec()
+        };
+        self.stems
+            .traverse::<AxisUp>(self.roots.iter())
+            .track_self()
+            .for_each(
+                |(parent, parent_stem, parent_transform, ..),
+                 _,
+                 (this, stem, transform, is_root, organ, dormant),
+                 _| {
+                    let (Some(organ), false) = (organ, dormant) else {
+                        return;
+                    };
+                    let mesh = plant_meshes.entry((organ.plant, *is_root)).or_default();
+                    let parent = (*parent, organ.plant, *is_root);
+                    // Continue the parent's tube where the stem goes on straight, and start a
+                    // new tube perpendicular to the stem where it branches off at an angle or
+                    // grows from a parent without a tube, like the base of the plant.
+                    let parent_direction = parent_transform.rotation * Vec3::Y;
+                    let direction = transform.rotation * Vec3::Y;
+                    let a =
+                        if parent_stem.size > 0.0 && parent_direction.dot(direction) > BRANCH_COS {
+                            rings
+                                .entry(parent)
+                                .or_insert_with(|| {
+                                    add_ring(mesh, parent_stem, parent_transform, *is_root)
+                                })
+                                .clone()
+                        } else {
+                            let start = Transform::from_translation(parent_transform.translation)
+                                .with_rotation(transform.rotation);
+                            add_ring(mesh, stem, &start, *is_root)
+                        };
+                    let ring = add_ring(mesh, stem, transform, *is_root);
+                    mesh.set_label(Some((**organ).into()));
+                    for i in 0..RING_RESOLUTION {
+                        let j = (i + 1) % RING_RESOLUTION;
+                        mesh.add_face((a[i], a[j], ring[i]));
+                        mesh.add_face((ring[i], a[j], ring[j]));
+                    }
+                    rings.insert((*this, organ.plant, *is_root), ring);
+                },
+            );
+
+        for (leaflet, organ) in &self.leaflets {
+            let Some(organ) = organ else {
+                continue;
+            };
+            let mesh = plant_meshes.entry((organ.plant, false)).or_default();
+            let midrib = leaflet
+                .midrib
+                .iter()
+                .filter_map(|particle| self.transforms.get(*particle).ok())
+                .copied()
+                .collect_vec();
+            mesh.set_label(Some((*organ).into()));
+            leaflet.shape.add_blade(mesh, &midrib, &leaflet.condition);
+        }
+        // Flowers and fruits are borne by the particle at the end of their pedicel.
+        for (flower, transform, pedicel) in &self.flowers {
+            let Some(pedicel) = pedicel else {
+                continue;
+            };
+            let mesh = plant_meshes.entry((pedicel.plant, false)).or_default();
+            mesh.set_label(Some(pedicel.part(OrganKind::Flower).into()));
+            flower.add_mesh(mesh, transform);
+        }
+        for (fruit, transform, pedicel) in &self.fruits {
+            let Some(pedicel) = pedicel else {
+                continue;
+            };
+            let mesh = plant_meshes.entry((pedicel.plant, false)).or_default();
+            mesh.set_label(Some(pedicel.part(OrganKind::Fruit).into()));
+            fruit.add_mesh(mesh, transform);
+        }
+
+        for mesh in plant_meshes.values_mut() {
+            mesh.update_normals();
+        }
+        plant_meshes
+    }
+}
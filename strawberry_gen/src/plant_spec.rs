@@ -0,0 +1,170 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    FlowerSpec, FruitSpec, GrowthSpec, LeafHealthSpec, LeafSpec, RootSpec, RosetteSpec, RunnerSpec,
+    Tropism, TrussSpec,
+};
+
+/// A value that varies from organ to organ around `mean` by at most `spread`.
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub struct SizeDistribution {
+    pub mean: f32,
+    pub spread: f32,
+}
+impl SizeDistribution {
+    pub fn new(mean: f32, spread: f32) -> Self {
+        Self { mean, spread }
+    }
+    pub fn constant(value: f32) -> Self {
+        Self::new(value, 0.0)
+    }
+    /// The same distribution with mean and spread scaled by `factor`.
+    pub fn scaled(&self, factor: f32) -> Self {
+        Self::new(self.mean * factor, self.spread * factor)
+    }
+    /// Draws a value uniformly from `mean ± spread`.
+    pub fn sample(&self, rng: &mut impl Rng) -> f32 {
+        if self.spread > 0.0 {
+            rng.gen_range(self.mean - self.spread..=self.mean + self.spread)
+        } else {
+            self.mean
+        }
+    }
+    /// Draws a value and rounds it to a non-negative count.
+    pub fn sample_count(&self, rng: &mut impl Rng) -> u32 {
+        self.sample(rng).round().max(0.0) as u32
+    }
+}
+impl Default for SizeDistribution {
+    fn default() -> Self {
+        Self::constant(1.0)
+    }
+}
+
+/// How many of an organ grow on each parent organ and how big they are.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct OrganSpec {
+    /// Number of organs per parent organ.
+    pub count: SizeDistribution,
+    /// Number of stem segments (particles) the organ is built from.
+    pub segments: u32,
+    /// Length of the whole organ, split evenly between the segments.
+    pub length: SizeDistribution,
+    /// Radius of the stem tube.
+    pub size: SizeDistribution,
+    /// Angle in radians each segment leans away from its parent, around a random axis.
+    pub rotation: SizeDistribution,
+    /// How the segments bend towards the light, with gravity and under their load.
+    pub tropism: Tropism,
+}
+impl OrganSpec {
+    pub fn new(
+        count: SizeDistribution,
+        segments: u32,
+        length: SizeDistribution,
+        size: SizeDistribution,
+    ) -> Self {
+        Self {
+            count,
+            segments,
+            length,
+            size,
+            rotation: SizeDistribution::constant(0.0),
+            tropism: Tropism::default(),
+        }
+    }
+    pub fn with_rotation(mut self, rotation: SizeDistribution) -> Self {
+        self.rotation = rotation;
+        self
+    }
+    pub fn with_tropism(mut self, tropism: Tropism) -> Self {
+        self.tropism = tropism;
+        self
+    }
+    /// The same organ with its length scaled by `factor`.
+    pub fn scaled(&self, factor: f32) -> Self {
+        let mut organ = self.clone();
+        organ.length = self.length.scaled(factor);
+        organ
+    }
+}
+
+/// Describes the topology and organ sizes of a [`StrawberryPlant`](crate::StrawberryPlant).
+///
+/// Counts are nested: every crown carries `petiole.count` petioles and `truss.count` trusses,
+/// and every petiole ends in `leaflet.count` leaflets. The flowers and fruits on a truss follow
+/// from its `inflorescence` branching; every flower is drawn from `flower` and the fruit it sets
+/// from `fruit`.
+///
+/// All random variation is drawn from a generator seeded with `seed`, so the same spec always
+/// produces the same plant.
+#[derive(Component, Debug, Clone, PartialEq, Reflect)]
+pub struct PlantSpec {
+    pub seed: u64,
+    pub crown: OrganSpec,
+    pub rosette: RosetteSpec,
+    pub petiole: OrganSpec,
+    pub leaflet: OrganSpec,
+    pub leaf: LeafSpec,
+    /// Senescence, disease and damage of the leaflets.
+    pub leaf_health: LeafHealthSpec,
+    /// The peduncle of each truss, up to its first branching node.
+    pub truss: OrganSpec,
+    pub inflorescence: TrussSpec,
+    pub flower: FlowerSpec,
+    pub fruit: FruitSpec,
+    pub runner: RunnerSpec,
+    /// Root system below the soil, not generated when `None`.
+    pub roots: Option<RootSpec>,
+    pub growth: GrowthSpec,
+    /// Direction towards the light, which phototropic stems turn to.
+    pub light: Vec3,
+}
+impl Default for PlantSpec {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            crown: OrganSpec::new(
+                SizeDistribution::constant(1.0),
+                1,
+                SizeDistribution::new(0.2, 0.05),
+                SizeDistribution::new(0.15, 0.02),
+            ),
+            rosette: RosetteSpec::default(),
+            petiole: OrganSpec::new(
+                SizeDistribution::new(5.0, 1.0),
+                4,
+                SizeDistribution::new(2.0, 0.4),
+                SizeDistribution::new(0.03, 0.005),
+            )
+            .with_rotation(SizeDistribution::new(0.1, 0.1))
+            // Petioles grow up and out, then arch over under the weight of their leaflets.
+            .with_tropism(Tropism::new(0.0, -0.15, 0.04)),
+            leaflet: OrganSpec::new(
+                SizeDistribution::constant(3.0),
+                3,
+                SizeDistribution::new(0.8, 0.15),
+                SizeDistribution::new(0.01, 0.0),
+            )
+            .with_rotation(SizeDistribution::new(0.05, 0.05)),
+            leaf: LeafSpec::default(),
+            leaf_health: LeafHealthSpec::default(),
+            truss: OrganSpec::new(
+                SizeDistribution::new(2.0, 1.0),
+                3,
+                SizeDistribution::new(1.2, 0.3),
+                SizeDistribution::new(0.025, 0.005),
+            )
+            .with_rotation(SizeDistribution::new(0.15, 0.1))
+            .with_tropism(Tropism::new(0.0, 0.0, 0.02)),
+            inflorescence: TrussSpec::default(),
+            flower: FlowerSpec::default(),
+            fruit: FruitSpec::default(),
+            runner: RunnerSpec::default(),
+            roots: None,
+            growth: GrowthSpec::default(),
+            light: Vec3::Y,
+        }
+    }
+}
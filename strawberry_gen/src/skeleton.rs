@@ -0,0 +1,532 @@
+use std::{collections::HashMap, fmt, path::Path};
+
+use aery::prelude::*;
+use bevy::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+
+use crate::{
+    spawn_blossom, spawn_inflorescence, spawn_leaflets, spawn_rosette, AxisUp, BendConstraint,
+    ConstraintToConstraint, EdgeConstraint, Growth, Leaflet, Organ, OrganKind, PartOf,
+    ParticleBundle, PlantSpec, RootStem, Stem, StemChain, P0, P1, P2,
+};
+
+/// A node of a measured skeleton, like a point of a phenotyping scan.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkeletonNode {
+    pub id: u32,
+    /// The node this one grows from, `None` for the base of the plant.
+    pub parent: Option<u32>,
+    pub position: Vec3,
+    /// Radius of the stem between the parent and this node, if it was measured.
+    pub radius: Option<f32>,
+    /// Organ the stem between the parent and this node belongs to, if it was labelled.
+    pub organ: Option<OrganKind>,
+}
+
+/// A measured plant architecture, used instead of the crown/rosette layout of [`PlantSpec`]
+/// when present on a [`StrawberryPlant`](crate::StrawberryPlant).
+///
+/// Every node becomes a particle whose [`Stem`] is fitted so that the stem transforms put it
+/// at its measured position, relative to the base node. The organs the measurement leaves out
+/// are filled in from the [`PlantSpec`] at the nodes without children: a rosette on a crown,
+/// leaflets on a petiole, the cyme on a peduncle and a flower or fruit on a pedicel. Nodes
+/// without a label are taken as crown.
+///
+/// Skeletons are read from CSV with one `id,parent,x,y,z[,radius][,organ]` row per node, an
+/// empty parent or `-1` marking the base, or from JSON:
+///
+/// ```text
+/// {"nodes": [{"id": 0, "position": [0, 0, 0]},
+///            {"id": 1, "parent": 0, "position": [0, 0.4, 0.1], "radius": 0.1, "organ": "crown"}]}
+/// ```
+#[derive(Component, Debug, Clone)]
+pub struct MeasuredSkeleton {
+    /// Ordered so that every parent comes before its children.
+    nodes: Vec<SkeletonNode>,
+    parents: Vec<Option<usize>>,
+}
+
+/// Shortest distance between a node and its parent that a stem is fitted to.
+const MIN_SEGMENT_LENGTH: f32 = 1e-4;
+
+#[derive(Debug)]
+pub enum SkeletonError {
+    Io(std::io::Error),
+    Parse {
+        line: usize,
+        message: String,
+    },
+    Json(serde_json::Error),
+    /// The nodes do not form a single tree, or a node lies on its parent.
+    Invalid(String),
+}
+impl fmt::Display for SkeletonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "cannot read skeleton: {error}"),
+            Self::Parse { line, message } => write!(f, "line {line}: {message}"),
+            Self::Json(error) => write!(f, "invalid skeleton JSON: {error}"),
+            Self::Invalid(message) => write!(f, "invalid skeleton: {message}"),
+        }
+    }
+}
+impl std::error::Error for SkeletonError {}
+impl From<std::io::Error> for SkeletonError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+impl From<serde_json::Error> for SkeletonError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Json(error)
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonSkeleton {
+    nodes: Vec<JsonNode>,
+}
+
+#[derive(Deserialize)]
+struct JsonNode {
+    id: u32,
+    #[serde(default)]
+    parent: Option<u32>,
+    position: [f32; 3],
+    #[serde(default)]
+    radius: Option<f32>,
+    #[serde(default)]
+    organ: Option<String>,
+}
+
+impl MeasuredSkeleton {
+    /// Checks that the nodes form a single tree without coincident nodes and orders them from
+    /// the base up.
+    pub fn new(nodes: Vec<SkeletonNode>) -> Result<Self, SkeletonError> {
+        let invalid = |message: String| Err(SkeletonError::Invalid(message));
+        let mut index = HashMap::new();
+        for (i, node) in nodes.iter().enumerate() {
+            if index.insert(node.id, i).is_some() {
+                return invalid(format!("node {} appears twice", node.id));
+            }
+        }
+        let mut children = vec![Vec::new(); nodes.len()];
+        let mut roots = Vec::new();
+        for (i, node) in nodes.iter().enumerate() {
+            match node.parent {
+                Some(parent) => match index.get(&parent) {
+                    Some(&parent) => children[parent].push(i),
+                    None => return invalid(format!("parent {parent} of {} is missing", node.id)),
+                },
+                None => roots.push(i),
+            }
+        }
+        let &[root] = roots.as_slice() else {
+            return invalid(format!("expected one base node, found {}", roots.len()));
+        };
+
+        let mut order = vec![root];
+        let mut next = 0;
+        while let Some(&i) = order.get(next) {
+            order.extend(&children[i]);
+            next += 1;
+        }
+        if order.len() < nodes.len() {
+            return invalid("the parent links form a cycle".into());
+        }
+        let ordered = (order.iter().enumerate())
+            .map(|(position, &i)| (i, position))
+            .collect::<HashMap<_, _>>();
+        let mut parents = Vec::with_capacity(order.len());
+        for &i in &order {
+            let node = &nodes[i];
+            let parent = node.parent.map(|parent| index[&parent]);
+            if let Some(parent) = parent {
+                // A stem needs a direction, which coincident nodes do not give it.
+                if node.position.distance(nodes[parent].position) < MIN_SEGMENT_LENGTH {
+                    return invalid(format!(
+                        "node {} lies on its parent {}",
+                        node.id, nodes[parent].id
+                    ));
+                }
+            }
+            parents.push(parent.map(|parent| ordered[&parent]));
+        }
+        let mut nodes = nodes.into_iter().map(Some).collect::<Vec<_>>();
+        let nodes = order.iter().filter_map(|&i| nodes[i].take()).collect();
+        Ok(Self { nodes, parents })
+    }
+
+    /// Reads a skeleton from a `.json` file, or from CSV otherwise.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SkeletonError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => Self::from_json(&text),
+            _ => Self::from_csv(&text),
+        }
+    }
+
+    /// Parses `id,parent,x,y,z[,radius][,organ]` rows. A header row, blank lines and lines
+    /// starting with `#` are skipped.
+    pub fn from_csv(text: &str) -> Result<Self, SkeletonError> {
+        let mut nodes = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| SkeletonError::Parse {
+                line: index + 1,
+                message,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+            if nodes.is_empty() && fields[0].parse::<f32>().is_err() {
+                // The header.
+                continue;
+            }
+            if fields.len() < 5 {
+                return Err(error(format!(
+                    "expected at least 5 fields, found {}",
+                    fields.len()
+                )));
+            }
+            let number = |i: usize| {
+                fields[i]
+                    .parse::<f32>()
+                    .map_err(|_| error(format!("invalid number `{}`", fields[i])))
+            };
+            let field = |i: usize| fields.get(i).copied().filter(|field| !field.is_empty());
+            let id = fields[0]
+                .parse()
+                .map_err(|_| error(format!("invalid id `{}`", fields[0])))?;
+            let parent = match fields[1] {
+                "" | "-1" => None,
+                parent => Some(
+                    parent
+                        .parse()
+                        .map_err(|_| error(format!("invalid parent `{parent}`")))?,
+                ),
+            };
+            let radius = match field(5) {
+                Some(_) => Some(number(5)?),
+                None => None,
+            };
+            let organ = field(6).map(str::parse).transpose().map_err(error)?;
+            nodes.push(SkeletonNode {
+                id,
+                parent,
+                position: Vec3::new(number(2)?, number(3)?, number(4)?),
+                radius,
+                organ,
+            });
+        }
+        Self::new(nodes)
+    }
+
+    /// Parses a `{"nodes": [...]}` object whose nodes have an `id`, a `position` array and
+    /// optionally a `parent`, `radius` and `organ`.
+    pub fn from_json(text: &str) -> Result<Self, SkeletonError> {
+        let skeleton: JsonSkeleton = serde_json::from_str(text)?;
+        let nodes = skeleton
+            .nodes
+            .into_iter()
+            .map(|node| {
+                Ok(SkeletonNode {
+                    id: node.id,
+                    parent: node.parent,
+                    position: Vec3::from(node.position),
+                    radius: node.radius,
+                    organ: node
+                        .organ
+                        .as_deref()
+                        .map(str::parse)
+                        .transpose()
+                        .map_err(SkeletonError::Invalid)?,
+                })
+            })
+            .collect::<Result<_, SkeletonError>>()?;
+        Self::new(nodes)
+    }
+
+    /// The nodes, ordered so that every parent comes before its children.
+    pub fn nodes(&self) -> &[SkeletonNode] {
+        &self.nodes
+    }
+
+    /// The organ of the stem ending in the node at `index`, with untagged nodes as crown.
+    fn kind(&self, index: usize) -> OrganKind {
+        self.nodes[index].organ.unwrap_or(OrganKind::Crown)
+    }
+
+    /// Fits the stem ending in every node, in the order of [`nodes`](Self::nodes), so that
+    /// [`Stem::rotation`] and [`Stem::length`] lead from the parent to the measured position.
+    /// Each frame is the parent frame turned by the smallest rotation onto the segment, so the
+    /// stems do not twist. Unmeasured radii take the mean size of the organ in `spec`. The base
+    /// gets a stem of zero length.
+    pub fn fit(&self, spec: &PlantSpec) -> Vec<Stem> {
+        let mut frames: Vec<Quat> = Vec::with_capacity(self.nodes.len());
+        let mut stems = Vec::with_capacity(self.nodes.len());
+        for (i, node) in self.nodes.iter().enumerate() {
+            let size = node
+                .radius
+                .unwrap_or_else(|| default_size(spec, self.kind(i)));
+            let Some(parent) = self.parents[i] else {
+                frames.push(Quat::IDENTITY);
+                stems.push(Stem::new(size, 0.0, Quat::IDENTITY));
+                continue;
+            };
+            let parent_frame = frames[parent];
+            let segment = node.position - self.nodes[parent].position;
+            let frame = match segment.try_normalize() {
+                Some(direction) => {
+                    Quat::from_rotation_arc(parent_frame * Vec3::Y, direction) * parent_frame
+                }
+                None => parent_frame,
+            };
+            frames.push(frame);
+            stems.push(Stem::new(
+                size,
+                segment.length(),
+                parent_frame.inverse() * frame,
+            ));
+        }
+        stems
+    }
+}
+
+fn default_size(spec: &PlantSpec, kind: OrganKind) -> f32 {
+    let organ = match kind {
+        OrganKind::Crown | OrganKind::Internode => &spec.crown,
+        OrganKind::Petiole => &spec.petiole,
+        OrganKind::Leaflet => &spec.leaflet,
+        OrganKind::Peduncle => &spec.truss,
+        OrganKind::Pedicel | OrganKind::Flower | OrganKind::Fruit | OrganKind::Calyx => {
+            &spec.inflorescence.pedicel
+        }
+        OrganKind::Runner => &spec.runner.internode,
+        OrganKind::Root => match &spec.roots {
+            Some(roots) => &roots.primary,
+            None => &spec.crown,
+        },
+    };
+    organ.size.mean
+}
+
+/// Spawns the particles of `skeleton` hanging off `base`, with the base node on `base.tip`,
+/// and fills in the unmeasured organs from `spec`. `rotation` turns the whole skeleton
+/// relative to `base`.
+pub(crate) fn spawn_skeleton(
+    commands: &mut Commands,
+    rng: &mut ChaCha8Rng,
+    base: &StemChain,
+    spec: &PlantSpec,
+    skeleton: &MeasuredSkeleton,
+    rotation: Quat,
+) {
+    let nodes = skeleton.nodes();
+    let stems = skeleton.fit(spec);
+    // Flowers, fruits and calyxes are borne at the end of their pedicel.
+    let stem_kind = |i: usize| match skeleton.kind(i) {
+        OrganKind::Flower | OrganKind::Fruit | OrganKind::Calyx => OrganKind::Pedicel,
+        kind => kind,
+    };
+    let mut children = vec![Vec::new(); nodes.len()];
+    for (i, parent) in skeleton.parents.iter().enumerate() {
+        if let Some(parent) = parent {
+            children[*parent].push(i);
+        }
+    }
+
+    // A node continues the organ of its parent if it is the first child of the same kind, and
+    // starts an organ named after itself otherwise.
+    let mut organ_starts = (0..nodes.len()).collect::<Vec<_>>();
+    let mut organ_names = vec![String::new(); nodes.len()];
+    let mut chains: Vec<StemChain> = Vec::with_capacity(nodes.len());
+    for (i, (node, stem)) in nodes.iter().zip(stems).enumerate() {
+        let Some(parent) = skeleton.parents[i] else {
+            chains.push(base.clone());
+            continue;
+        };
+        let kind = stem_kind(i);
+        let continues = skeleton.parents[parent].is_some()
+            && stem_kind(parent) == kind
+            && children[parent].iter().find(|&&c| stem_kind(c) == kind) == Some(&i);
+        if continues {
+            organ_starts[i] = organ_starts[parent];
+            organ_names[i] = organ_names[parent].clone();
+        } else {
+            organ_names[i] = format!("{kind:?} {}", node.id);
+        }
+
+        let rotation = if skeleton.parents[parent].is_none() {
+            rotation * stem.rotation
+        } else {
+            stem.rotation
+        };
+        let parent_chain = &chains[parent];
+        let particle = commands
+            .spawn((
+                Name::new(format!("Node {}", node.id)),
+                Growth::new(stem.size, stem.length),
+                Stem::new(stem.size, stem.length, rotation),
+                Organ::new(kind, &organ_names[i], base.plant),
+                ParticleBundle::default(),
+            ))
+            .set::<AxisUp>(parent_chain.tip)
+            .set::<PartOf>(base.plant)
+            .id();
+        if kind == OrganKind::Root {
+            commands.entity(particle).insert(RootStem);
+        }
+        if let Some(grandparent) = skeleton.parents[parent] {
+            commands
+                .spawn((
+                    Name::new(format!("Node {} B", node.id)),
+                    BendConstraint::from_rest_angle(0.0),
+                ))
+                .set::<P0>(chains[grandparent].tip)
+                .set::<P1>(parent_chain.tip)
+                .set::<P2>(particle)
+                .set::<PartOf>(base.plant);
+        }
+        let mut constraint = commands.spawn((
+            Name::new(format!("Node {} C", node.id)),
+            EdgeConstraint::from_rest_length(stem.length),
+        ));
+        constraint
+            .set::<P0>(parent_chain.tip)
+            .set::<P1>(particle)
+            .set::<PartOf>(base.plant);
+        if let Some(previous) = parent_chain.tip_constraint {
+            constraint.set::<ConstraintToConstraint>(previous);
+        }
+        let mut nodes = if continues {
+            parent_chain.nodes.clone()
+        } else {
+            Vec::new()
+        };
+        nodes.push(particle);
+        let frame = parent_chain.frame * rotation;
+        chains.push(StemChain {
+            nodes,
+            tip: particle,
+            tip_constraint: Some(constraint.id()),
+            plant: base.plant,
+            frame,
+            height: parent_chain.height + (frame * Vec3::Y).y * stem.length,
+        });
+    }
+
+    for (i, chain) in chains.iter().enumerate() {
+        let Some(parent) = skeleton.parents[organ_starts[i]] else {
+            continue;
+        };
+        let name = &organ_names[i];
+        let ends_organ = !children[i]
+            .iter()
+            .any(|&c| organ_starts[c] == organ_starts[i]);
+        if stem_kind(i) == OrganKind::Leaflet && ends_organ {
+            let midrib = std::iter::once(chains[parent].tip)
+                .chain(chain.nodes.iter().copied())
+                .collect();
+            let shape = spec.leaf.sample_shape(rng);
+            commands.entity(chain.nodes[0]).insert(Leaflet {
+                condition: spec.leaf_health.sample(rng, &shape),
+                shape,
+                midrib,
+            });
+        }
+        if !children[i].is_empty() {
+            continue;
+        }
+        match skeleton.kind(i) {
+            OrganKind::Crown => spawn_rosette(commands, rng, name, chain, spec),
+            OrganKind::Petiole => spawn_leaflets(commands, rng, name, chain, spec),
+            OrganKind::Peduncle => spawn_inflorescence(commands, rng, name, chain, spec),
+            OrganKind::Pedicel => {
+                let maturity = spec.fruit.maturity.sample(rng);
+                spawn_blossom(commands, rng, chain.tip, spec, maturity);
+            }
+            OrganKind::Flower => {
+                // Still in bloom, before the fruit sets.
+                let maturity = spec.fruit.maturity.sample(rng);
+                let maturity = maturity.min(-0.5 * spec.inflorescence.maturity_step);
+                spawn_blossom(commands, rng, chain.tip, spec, maturity);
+            }
+            OrganKind::Fruit | OrganKind::Calyx => {
+                let maturity = spec.fruit.maturity.sample(rng).max(0.0);
+                spawn_blossom(commands, rng, chain.tip, spec, maturity);
+            }
+            OrganKind::Leaflet | OrganKind::Runner | OrganKind::Root | OrganKind::Internode => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{
+        test_utils::{spawn_plant, test_app},
+        ParticlePosition,
+    };
+
+    #[test]
+    fn test_fitted_stems_reproduce_measured_nodes() {
+        let skeleton = MeasuredSkeleton::from_csv(
+            "id,parent,x,y,z,radius,organ
+            0,-1,1.0,0.0,2.0,,
+            1,0,1.0,0.5,2.0,0.1,crown
+            2,1,1.3,0.9,2.1,,petiole
+            3,2,1.5,1.0,2.6,,petiole
+            4,1,0.6,0.8,1.8,,peduncle
+            5,4,0.2,0.3,1.5,0.02,fruit",
+        )
+        .unwrap();
+        let mut app = test_app();
+        spawn_plant(
+            &mut app,
+            (
+                PlantSpec::default(),
+                skeleton.clone(),
+                Transform::from_xyz(1.0, 0.0, 2.0),
+            ),
+        );
+        let positions = app
+            .world
+            .run_system_once(|nodes: Query<(&Name, &ParticlePosition)>| {
+                nodes
+                    .iter()
+                    .map(|(name, position)| (name.to_string(), position.0))
+                    .collect::<HashMap<_, _>>()
+            });
+        for node in skeleton.nodes() {
+            if node.parent.is_some() {
+                let position = positions[&format!("Node {}", node.id)];
+                assert!(position.distance(node.position) < 1e-4, "node {}", node.id);
+            }
+        }
+        // The petiole got its leaflets and the truss its fruit.
+        assert!(positions
+            .keys()
+            .any(|name| name.starts_with("Petiole 2 Leaflet")));
+        let fruits = app
+            .world
+            .run_system_once(|fruits: Query<(), With<crate::Fruit>>| fruits.iter().count());
+        assert_eq!(fruits, 1);
+    }
+
+    #[test]
+    fn test_coincident_nodes_are_rejected() {
+        let skeleton = MeasuredSkeleton::from_csv(
+            "id,parent,x,y,z,radius,organ
+            0,-1,0.0,0.0,0.0,,
+            1,0,0.0,0.5,0.0,0.1,crown
+            2,1,0.0,0.5,0.0,,petiole",
+        );
+        assert!(matches!(skeleton, Err(SkeletonError::Invalid(_))));
+    }
+}
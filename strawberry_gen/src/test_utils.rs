@@ -0,0 +1,20 @@
+use aery::prelude::*;
+use bevy::prelude::*;
+
+use crate::{StrawberryPlant, StrawberryPlantPlugin};
+
+/// An app that generates the plants spawned in it, without rendering or physics.
+pub(crate) fn test_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, Aery, StrawberryPlantPlugin));
+    app
+}
+
+/// Spawns a [`StrawberryPlant`] with `bundle` and generates it. The daughter plants on its
+/// runners are generated one update after it, so this runs two.
+pub(crate) fn spawn_plant(app: &mut App, bundle: impl Bundle) -> Entity {
+    let plant = app.world.spawn((StrawberryPlant, bundle)).id();
+    app.update();
+    app.update();
+    plant
+}
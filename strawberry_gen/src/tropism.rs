@@ -0,0 +1,227 @@
+use std::f32::consts::PI;
+
+use aery::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{AxisUp, BendConstraint, Fruit, Organ, OrganKind, PlantRoot, PlantSpec, Stem, P2};
+
+/// Thickness of a leaflet blade, which weighs on the petiole.
+const BLADE_THICKNESS: f32 = 0.02;
+
+/// How the segments of an organ bend while it is generated. Every rate is a turn in radians
+/// per unit of segment length, scaled by the sine of the angle to the direction turned to, so
+/// a segment already pointing there stays straight.
+///
+/// Stems bend once, when they are spawned, and keep the bent shape as their rest shape.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Reflect)]
+pub struct Tropism {
+    /// Turn towards [`PlantSpec::light`].
+    pub phototropism: f32,
+    /// Turn towards the ground, or away from it when negative, as shoots do.
+    pub gravitropism: f32,
+    /// Turn towards the ground under the weight the segment carries: the stems, leaflet blades
+    /// and fruits beyond it, by volume, relative to its cross section.
+    pub sag: f32,
+    /// Bend down to the ground, the horizontal plane through the plant's base, and creep along
+    /// it: every segment keeps its heading and turns so its tip ends as close to the ground as
+    /// its length allows. Applied to the rest frames as the segments are spawned, so the pose of
+    /// a runner, and of the daughter plants on it, is known up front. The other turns come
+    /// after.
+    pub creep: bool,
+}
+impl Tropism {
+    pub fn new(phototropism: f32, gravitropism: f32, sag: f32) -> Self {
+        Self {
+            phototropism,
+            gravitropism,
+            sag,
+            creep: false,
+        }
+    }
+    pub fn with_creep(mut self, creep: bool) -> Self {
+        self.creep = creep;
+        self
+    }
+
+    /// The turn of a segment pointing in `direction` in the plant's world frame.
+    fn turn(&self, direction: Vec3, length: f32, load: f32, size: f32, light: Vec3) -> Quat {
+        let gravity = if self.gravitropism > 0.0 {
+            Vec3::NEG_Y
+        } else {
+            Vec3::Y
+        };
+        let cross_section = PI * size.max(1e-4).powi(2);
+        let sag = self.sag * load / cross_section;
+        turn_towards(direction, Vec3::NEG_Y, sag * length)
+            * turn_towards(direction, light, self.phototropism * length)
+            * turn_towards(direction, gravity, self.gravitropism.abs() * length)
+    }
+}
+
+/// The rotation turning `direction`, of a segment of `length` starting `height` above the
+/// ground, so the segment ends on the ground or as close to it as it can, keeping its heading.
+pub(crate) fn creep_towards_ground(direction: Vec3, length: f32, height: f32) -> Quat {
+    let Some(heading) = direction.reject_from(Vec3::Y).try_normalize() else {
+        return Quat::IDENTITY;
+    };
+    let slope = (-height / length.max(1e-4)).clamp(-1.0, 1.0);
+    let target = heading * (1.0 - slope * slope).sqrt() + Vec3::Y * slope;
+    Quat::from_rotation_arc(direction, target)
+}
+
+/// The rotation turning `direction` towards `target` by `rate` times the sine of the angle
+/// between them, without overshooting.
+fn turn_towards(direction: Vec3, target: Vec3, rate: f32) -> Quat {
+    let Some(axis) = direction.cross(target).try_normalize() else {
+        return Quat::IDENTITY;
+    };
+    let angle = direction.angle_between(target);
+    Quat::from_axis_angle(axis, (rate * angle.sin()).clamp(0.0, angle))
+}
+
+/// Weight of a stem with what it bears at its end, by volume.
+fn weight(stem: &Stem, fruit: Option<&Fruit>, organ: Option<&Organ>) -> f32 {
+    let mut weight = PI * stem.size.powi(2) * stem.length;
+    if organ.is_some_and(|organ| organ.kind == OrganKind::Leaflet) {
+        // The strip of blade on both sides of the midrib.
+        weight += BLADE_THICKNESS * 2.0 * stem.length.powi(2);
+    }
+    weight + fruit.map_or(0.0, Fruit::volume)
+}
+
+/// Bends the newly spawned stems with a [`Tropism`]. The frames and particle positions are worked
+/// out from the plant root up, so each stem bends from where its parent has bent to.
+#[allow(clippy::type_complexity)]
+fn bend_stems(
+    roots: Query<(Entity, &PlantRoot), Root<AxisUp>>,
+    plants: Query<Option<&PlantSpec>>,
+    added: Query<(), Added<Tropism>>,
+    mut stems: Query<(
+        (
+            Entity,
+            &mut Stem,
+            &mut Transform,
+            Option<Ref<Tropism>>,
+            Option<&Fruit>,
+            Option<&Organ>,
+        ),
+        Relations<AxisUp>,
+    )>,
+) {
+    if added.is_empty() {
+        return;
+    }
+    for (root, plant) in &roots {
+        let light = plants
+            .get(plant.0)
+            .ok()
+            .flatten()
+            .map_or(Vec3::Y, |spec| spec.light)
+            .normalize_or_zero();
+
+        // Sum the weight beyond every stem, from the tips down.
+        let mut edges = Vec::new();
+        let mut loads = HashMap::new();
+        stems.traverse::<AxisUp>([root]).track_self().for_each(
+            |(parent, ..), _, (entity, stem, _, _, fruit, organ), _| {
+                edges.push((*parent, *entity));
+                loads.insert(*entity, weight(stem, *fruit, *organ));
+            },
+        );
+        for (parent, entity) in edges.iter().rev() {
+            let load = loads[entity];
+            *loads.entry(*parent).or_default() += load;
+        }
+
+        let Ok(((_, _, root_transform, ..), _)) = stems.get(root) else {
+            continue;
+        };
+        let mut positions = HashMap::from([(root, root_transform.translation)]);
+        stems.traverse_mut::<AxisUp>([root]).track_self().for_each(
+            |(parent, _, parent_transform, ..), _, (entity, stem, transform, tropism, ..), _| {
+                let base = positions[parent];
+                let frame = parent_transform.rotation * stem.rotation;
+                let frame = match tropism {
+                    Some(tropism) if tropism.is_added() => {
+                        let turn = tropism.turn(
+                            frame * Vec3::Y,
+                            stem.length,
+                            loads[entity],
+                            stem.size,
+                            light,
+                        );
+                        stem.rotation = parent_transform.rotation.inverse() * turn * frame;
+                        turn * frame
+                    }
+                    _ => frame,
+                };
+                transform.rotation = frame;
+                positions.insert(*entity, base + frame * Vec3::Y * stem.length);
+            },
+        );
+    }
+}
+
+/// Keeps the rest angle of every bend constraint equal to the turn of the stem it ends in.
+fn sync_rest_angles(
+    mut constraints: Query<(&mut BendConstraint, Relations<P2>)>,
+    stems: Query<&Stem, Changed<Stem>>,
+) {
+    for (mut constraint, edges) in &mut constraints {
+        edges.join::<Up<P2>>(&stems).for_each(|stem| {
+            constraint.rest_angle = (stem.rotation * Vec3::Y).angle_between(Vec3::Y);
+        });
+    }
+}
+
+pub(crate) fn tropism_systems() -> impl IntoSystemConfigs<()> {
+    (bend_stems, sync_rest_angles).chain()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{
+        test_utils::{spawn_plant, test_app},
+        ParticlePosition, P0, P1,
+    };
+
+    #[allow(clippy::type_complexity)]
+    fn mean_fruit_height(spec: PlantSpec) -> f32 {
+        let mut app = test_app();
+        spawn_plant(&mut app, spec);
+
+        // The rest angles match the bent rest shape.
+        app.world.run_system_once(
+            |constraints: Query<(&BendConstraint, Relations<(P0, P1, P2)>)>,
+             particles: Query<&ParticlePosition>| {
+                for (constraint, edges) in &constraints {
+                    edges
+                        .join::<Up<P0>>(&particles)
+                        .join::<Up<P1>>(&particles)
+                        .join::<Up<P2>>(&particles)
+                        .for_each(|(a, b, c)| {
+                            let angle = BendConstraint::angle(a, b, c);
+                            assert!((angle - constraint.rest_angle).abs() < 1e-3);
+                        });
+                }
+            },
+        );
+        app.world
+            .run_system_once(|fruits: Query<&ParticlePosition, With<Fruit>>| {
+                fruits.iter().map(|position| position.y).sum::<f32>() / fruits.iter().len() as f32
+            })
+    }
+
+    #[test]
+    fn test_fruit_weight_makes_trusses_droop() {
+        let drooping = PlantSpec::default();
+        let mut stiff = drooping.clone();
+        stiff.truss.tropism = Tropism::default();
+        stiff.inflorescence.internode.tropism = Tropism::default();
+        stiff.inflorescence.pedicel.tropism = Tropism::default();
+        assert!(mean_fruit_height(drooping) < mean_fruit_height(stiff) - 0.1);
+    }
+}
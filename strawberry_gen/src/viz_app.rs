@@ -1,10 +1,10 @@
 use aery::prelude::*;
 use bevy::prelude::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
-use iter_tools::Itertools;
 
 use crate::{
-    AxisUp, ConstrainsPlugin, MeshMap, ParticlePosition, PlantPhysicsPlugin, Stem, StrawberryPlant,
+    ConstrainsPlugin, EnvironmentSpec, MeshMap, PartOf, ParticlePosition, PlantExporter,
+    PlantMeshBuilder, PlantPhysicsPlugin, PlantSpec, SceneLayout, StrawberryPlant,
     StrawberryPlantPlugin,
 };
 use bevy_panorbit_camera::{PanOrbitCamera, PanOrbitCameraPlugin};
@@ -18,10 +18,20 @@ pub fn viz_plant() {
         .add_plugins(PanOrbitCameraPlugin)
         .add_plugins(WorldInspectorPlugin::new())
         .add_plugins((PlantPhysicsPlugin, StrawberryPlantPlugin, ConstrainsPlugin))
+        .init_resource::<SceneLayout>()
+        .init_resource::<EnvironmentSpec>()
         .add_systems(Startup, setup)
         .add_systems(
             Update,
-            (update_config, draw_nodes, draw_mesh, debug_draw_mesh),
+            (
+                update_config,
+                toggle_roots,
+                export_plants,
+                draw_nodes,
+                spawn_plant_meshes,
+                draw_mesh,
+                debug_draw_mesh,
+            ),
         )
         .run();
 }
@@ -36,20 +46,36 @@ fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    layout: Res<SceneLayout>,
+    environment: Res<EnvironmentSpec>,
 ) {
+    let ground = layout.ground_size();
     commands.spawn((
         Camera3dBundle {
-            transform: Transform::from_xyz(0., 1.5, 12.).looking_at(Vec3::ZERO, Vec3::Y),
+            transform: Transform::from_xyz(0., 1.5, ground.y.max(12.))
+                .looking_at(Vec3::ZERO, Vec3::Y),
             ..default()
         },
         PanOrbitCamera::default(),
     ));
-    // plane
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Plane::from_size(5.0))),
-        material: materials.add(Color::rgb(0.3, 0.5, 0.3).into()),
+    let material = materials.add(StandardMaterial {
+        base_color: Color::WHITE,
+        double_sided: true,
+        cull_mode: None,
         ..default()
     });
+    for (prop, mut mesh) in environment.generate(&layout) {
+        mesh.update_normals();
+        commands.spawn((
+            Name::new(format!("{prop:?}")),
+            prop,
+            PbrBundle {
+                mesh: meshes.add(mesh.bevy_mesh()),
+                material: material.clone(),
+                ..default()
+            },
+        ));
+    }
 
     // light
     commands.spawn(PointLightBundle {
@@ -62,14 +88,70 @@ fn setup(
         ..default()
     });
 
-    commands.spawn(StrawberryPlant);
-    commands.spawn((
-        PlantMesh,
-        PbrBundle {
-            material: materials.add(Color::GREEN.with_a(0.3).into()),
+    layout.spawn(&mut commands, &PlantSpec::default());
+    commands.insert_resource(PlantMaterial(material));
+}
+
+/// Vertex colored material shared by all plant and prop meshes.
+#[derive(Resource)]
+struct PlantMaterial(Handle<StandardMaterial>);
+
+/// Spawns the shoot and root meshes of every new plant, as parts of the plant.
+fn spawn_plant_meshes(
+    mut commands: Commands,
+    plants: Query<Entity, Added<StrawberryPlant>>,
+    material: Res<PlantMaterial>,
+) {
+    for plant in &plants {
+        let bundle = PbrBundle {
+            material: material.0.clone(),
             ..default()
-        },
-    ));
+        };
+        commands
+            .spawn((PlantMesh(plant), bundle.clone()))
+            .set::<PartOf>(plant);
+        commands
+            .spawn((PlantMesh(plant), RootMesh, bundle))
+            .set::<PartOf>(plant);
+    }
+}
+
+/// Shows and hides the root system with R.
+fn toggle_roots(keyboard: Res<Input<KeyCode>>, mut roots: Query<&mut Visibility, With<RootMesh>>) {
+    if keyboard.just_pressed(KeyCode::R) {
+        for mut visibility in &mut roots {
+            *visibility = match *visibility {
+                Visibility::Hidden => Visibility::Inherited,
+                _ => Visibility::Hidden,
+            };
+        }
+    }
+}
+
+/// Saves every plant, the daughters on runners included, as glTF with G.
+fn export_plants(
+    keyboard: Res<Input<KeyCode>>,
+    plants: Query<Entity, With<StrawberryPlant>>,
+    exporter: PlantExporter,
+) {
+    if !keyboard.just_pressed(KeyCode::G) {
+        return;
+    }
+    for (plant, gltf) in exporter.export_all(&plants) {
+        let path = format!("plant_{}.glb", plant.index());
+        let gltf = match gltf {
+            Ok(Some(gltf)) => gltf,
+            Ok(None) => continue,
+            Err(error) => {
+                error!("Cannot export {path}: {error}");
+                continue;
+            }
+        };
+        match gltf.save(&path) {
+            Ok(()) => info!("Saved {path}"),
+            Err(error) => error!("Cannot save {path}: {error}"),
+        }
+    }
 }
 
 fn update_config(mut config: ResMut<GizmoConfig>, keyboard: Res<Input<KeyCode>>, time: Res<Time>) {
@@ -91,53 +173,32 @@ fn update_config(mut config: ResMut<GizmoConfig>, keyboard: Res<Input<KeyCode>>,
     }
 }
 
+/// A mesh of the plant, which despawns along with it.
+#[derive(Component)]
+struct PlantMesh(Entity);
+
+/// The root system, in its own mesh so it can be hidden.
 #[derive(Component)]
-struct PlantMesh;
+struct RootMesh;
 
 fn draw_mesh(
     mut commands: Commands,
-    mut target: Query<(Entity, &mut Handle<Mesh>), With<PlantMesh>>,
-    roots: Query<Entity, Root<AxisUp>>,
+    mut targets: Query<(Entity, &PlantMesh, Has<RootMesh>, &mut Handle<Mesh>)>,
     changed_particles: Query<Entity, Changed<ParticlePosition>>,
-    stems: Query<((&Stem, &Transform), Relations<AxisUp>)>,
+    builder: PlantMeshBuilder,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
     if changed_particles.is_empty() {
         return;
     }
+    let mut plant_meshes = builder.build();
 
-    let mut mesh = MeshMap::default();
-    let ring_resolution = 6;
-    let mut rings = Vec::new();
-    stems
-        .traverse::<AxisUp>(roots.iter())
-        .for_each(|(stem, transform), _| {
-            let ring = (0..ring_resolution)
-                .map(|i| {
-                    let angle = i as f32 / ring_resolution as f32 * std::f32::consts::TAU;
-                    let relative_transform = Transform::from_rotation(Quat::from_rotation_y(angle))
-                        * Transform::from_translation(Vec3::X * stem.size);
-                    let transform = **transform * relative_transform;
-                    mesh.add_vertex(transform.translation)
-                })
-                .collect_vec();
-            rings.push(ring);
-        });
-
-    for ab in rings.windows(2) {
-        let a = &ab[0];
-        let b = &ab[1];
-        for i in 0..ring_resolution {
-            let j = (i + 1) % ring_resolution;
-            mesh.add_face((a[i], a[j], b[i]));
-            mesh.add_face((b[i], a[j], b[j]));
-        }
+    // Update meshes
+    for (entity, PlantMesh(plant), is_root, mut handle) in &mut targets {
+        let mesh = plant_meshes.remove(&(*plant, is_root)).unwrap_or_default();
+        *handle = meshes.add(mesh.bevy_mesh());
+        commands.entity(entity).insert(mesh);
     }
-
-    // Update mesh
-    let mut target = target.single_mut();
-    *target.1 = meshes.add(mesh.bevy_mesh());
-    commands.entity(target.0).insert(mesh);
 }
 
 fn debug_draw_mesh(mesh_maps: Query<&MeshMap>, mut gizmos: Gizmos) {
@@ -155,3 +216,29 @@ fn debug_draw_mesh(mesh_maps: Query<&MeshMap>, mut gizmos: Gizmos) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    #[test]
+    fn test_r_toggles_the_roots() {
+        let mut world = World::new();
+        let roots = world.spawn((RootMesh, Visibility::Inherited)).id();
+        let shoot = world.spawn(Visibility::Inherited).id();
+        let press_r = |world: &mut World| {
+            let mut keyboard = Input::<KeyCode>::default();
+            keyboard.press(KeyCode::R);
+            world.insert_resource(keyboard);
+            world.run_system_once(toggle_roots);
+        };
+
+        press_r(&mut world);
+        assert_eq!(world.get::<Visibility>(roots), Some(&Visibility::Hidden));
+        assert_eq!(world.get::<Visibility>(shoot), Some(&Visibility::Inherited));
+        press_r(&mut world);
+        assert_eq!(world.get::<Visibility>(roots), Some(&Visibility::Inherited));
+    }
+}